@@ -1,14 +1,21 @@
 use std::{
   hash::{Hash, Hasher},
-  sync::Arc,
+  sync::{Arc, Weak},
 };
 
 use crate::*;
 
 pub mod cell;
 use cell::*;
+pub mod power;
+pub use power::*;
+pub mod roof;
+pub use roof::*;
 pub mod wall;
-use rand::{seq::SliceRandom, thread_rng, Rng};
+pub mod yard;
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, thread_rng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 pub mod room;
 pub use room::*;
 
@@ -22,7 +29,22 @@ pub struct Building {
   bounds: Option<Rect>,
   pub origin: Transform,
   pub navigated: AtomicBool,
-  arc: Box<Arc<Self>>,
+  /// Off until the player finds the fuse box or generator and turns it on.
+  powered: AtomicBool,
+  /// Everything about the layout is generated from this.
+  pub seed: u64,
+  rng: StdRng,
+  /// Walls changed since generation, replayed on top of the seed when loading a save.
+  pub wall_changes: RwLock<Vec<WallChange>>,
+  /// This building, for handing out from `&self`.
+  arc: Weak<Self>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WallChange {
+  pub coord: Coord,
+  pub dir: usize,
+  pub state: wall::State,
 }
 
 #[derive(Component)]
@@ -30,10 +52,12 @@ pub struct BuildingComponent {
   pub building: Arc<Building>,
 }
 
+/// Cells are centered on the building's origin and multiples of `CELL_SIZE` from it.
+const CELL_GRID: Grid = Grid::centered(CELL_SIZE);
+
 const PROPERTY_WIDTH: f32 = 200.;
 const PROPERTY_WIDTH_2: f32 = PROPERTY_WIDTH / 2.;
 const PROPERTY_HEIGHT: f32 = PROPERTY_WIDTH;
-const PROPERTY_HEIGHT_2: f32 = PROPERTY_HEIGHT / 2.;
 
 // children, avert your eyes...
 unsafe fn idc_to_static_mut<'a, T>(r: &'a T) -> &'static mut T {
@@ -44,47 +68,23 @@ unsafe fn idc_to_static_mut<'a, T>(r: &'a T) -> &'static mut T {
 }
 
 impl Building {
-  pub fn spawn(
-    commands: Commands,
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
-    zombie_material: ResMut<Assets<ZombieMaterial>>,
-    ass: Res<AssetServer>,
-  ) {
-    let origin = Transform::from_xyz(0., 0.1, 0.);
-    let bounds =
-      Rect::build(PROPERTY_WIDTH, PROPERTY_HEIGHT).enter_south_middle_at(&origin.translation);
-
-    Self::fabricate(
-      commands,
-      meshes,
-      materials,
-      zombie_material,
-      ass,
-      origin,
-      Some(bounds),
-    );
-  }
-
-  fn new<'a>(origin: Transform, bounds: Option<Rect>) -> (Arc<Self>, &'a mut Self) {
-    let arc = Arc::new(Self {
+  fn new<'a>(origin: Transform, bounds: Option<Rect>, seed: u64) -> (Arc<Self>, &'a mut Self) {
+    let arc = Arc::new_cyclic(|weak| Self {
       id: BUILDING_ID.fetch_add(1, Ordering::SeqCst),
       origin,
       bounds,
       cells: HashMap::new(),
       rooms: HashMap::new(),
       navigated: AtomicBool::new(false),
-
-      #[allow(invalid_value)]
-      arc: unsafe { std::mem::MaybeUninit::zeroed().assume_init() },
+      powered: AtomicBool::new(false),
+      seed,
+      rng: StdRng::seed_from_u64(seed),
+      wall_changes: RwLock::default(),
+      arc: weak.clone(),
     });
 
     // do a whole bunch of terrible unsafe things...
     let building = unsafe { idc_to_static_mut(&*arc) };
-    let bad_arc = std::mem::replace(&mut building.arc, Box::new(arc.clone()));
-    // Do not do cleanup on this "arc" - it is not an arc
-    std::mem::forget(bad_arc);
-
     // done with filthiness... Father, forgive me of my sins
     building.seed_room(Coord { x: 0, z: 0 });
 
@@ -92,19 +92,20 @@ impl Building {
   }
 
   fn arc(&self) -> Arc<Self> {
-    self.arc.as_ref().clone()
+    self.arc.upgrade().unwrap()
   }
 
-  fn fabricate(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut zombie_material: ResMut<Assets<ZombieMaterial>>,
-    ass: Res<AssetServer>,
-    origin: Transform,
-    bounds: Option<Rect>,
-  ) -> Entity {
-    let (arc, building) = Building::new(origin, bounds);
+  /// The property a building at `origin` is laid out on.
+  pub fn property(origin: &Transform) -> Rect {
+    Rect::build(PROPERTY_WIDTH, PROPERTY_HEIGHT)
+      .enter_south_middle_at(&origin.translation)
+      .rotated_about(origin.translation, origin.rotation)
+  }
+
+  /// Lays out the rooms, doors and navigation of a building. The same seed always gives the same
+  /// layout.
+  pub fn generate(origin: Transform, seed: u64, wall_changes: &[WallChange]) -> Arc<Self> {
+    let (arc, building) = Building::new(origin, Some(Self::property(&origin)), seed);
 
     for _ in 0..40 {
       building.seed_random_room(&arc);
@@ -112,53 +113,94 @@ impl Building {
 
     building.join_rooms();
     building.create_outside_doors();
+    for change in wall_changes {
+      building.set_wall(change.coord, change.dir, change.state);
+    }
     building.gen_navigation();
-    building.spawn_zombies(&mut commands, &mut meshes, &mut zombie_material);
+
+    arc
+  }
+
+  /// Generates a building and spawns it, with a zombie in every cell if `spawn_zombies`. With `bake`,
+  /// floors and walls are merged into a mesh per material and one collider. `lots` are the origins
+  /// of the buildings around it, whose yards it may share a fence with.
+  // half of these are the asset resources every fabricate in the tree passes down
+  #[allow(clippy::too_many_arguments)]
+  pub fn fabricate(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    zombie_material: &mut ResMut<Assets<ZombieMaterial>>,
+    ass: &Res<AssetServer>,
+    origin: Transform,
+    seed: u64,
+    wall_changes: &[WallChange],
+    lots: &[Transform],
+    spawn_zombies: bool,
+    bake: bool,
+  ) -> (Entity, Arc<Self>) {
+    let arc = Self::generate(origin, seed, wall_changes);
+    let building = &*arc;
+
+    if spawn_zombies {
+      building.spawn_zombies(commands, meshes, cache, zombie_material);
+    }
 
     let building_component = BuildingComponent {
       building: arc.clone(),
     };
 
-    let _ = ZONE_TX.send(ZItem::Building(arc.clone()));
-
-    // DEBUG
-    // building.debug_fabricate_nav(&mut commands, &mut meshes, &mut materials);
     // DEBUG
     for _ in 0..0 {
       ENTITIES
         .standing_lamp
-        .spawn(Transform::from_xyz(3., 1., 0.), &mut commands, &ass);
+        .spawn(Transform::from_xyz(3., 1., 0.), commands, ass);
       ENTITIES
         .sofa
-        .spawn(Transform::from_xyz(3., 1., 0.), &mut commands, &ass);
+        .spawn(Transform::from_xyz(3., 1., 0.), commands, ass);
       ENTITIES
         .fridge
-        .spawn(Transform::from_xyz(3., 1., 0.), &mut commands, &ass);
+        .spawn(Transform::from_xyz(3., 1., 0.), commands, ass);
     }
 
-    commands
+    let entity = commands
       .spawn_bundle(PbrBundle {
         transform: origin,
         ..default()
       })
       .with_children(|child_builder| {
-        for cell in building.cells.values() {
-          cell.fabricate(&building, child_builder, &mut meshes, &mut materials, &ass);
+        if bake {
+          let mut baker = Baker::default();
+          for coord in building.cells.keys().sorted() {
+            building.cells[coord].bake(building, &mut baker, materials, cache, ass);
+          }
+          baker.fabricate(child_builder, meshes);
+        } else {
+          // in order, so the physics sees the same building the same way every time
+          for coord in building.cells.keys().sorted() {
+            building.cells[coord].fabricate(building, child_builder, meshes, materials, cache, ass);
+          }
         }
+        yard::Yard::fabricate(building, lots, child_builder, meshes, materials, cache, ass);
+        building.fabricate_power(child_builder, meshes, materials, cache);
+        building.fabricate_roof(child_builder, meshes, materials, cache, bake);
       })
       .insert(building_component)
-      .id()
+      .id();
+
+    (entity, arc)
   }
 
-  #[allow(unused)]
-  fn debug_fabricate_nav(
-    &self,
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-  ) {
-    for cell in self.cells.values() {
-      cell.fabricate_nav(commands, meshes, materials);
+  /// Changes a wall after generation and remembers it for saving. Only takes effect visually the
+  /// next time the building is fabricated.
+  pub fn set_wall(&self, coord: Coord, dir: usize, state: wall::State) {
+    if let Some(cell) = self.cells.get(&coord) {
+      cell.wall_state.write()[dir] = state;
+      self
+        .wall_changes
+        .write()
+        .push(WallChange { coord, dir, state });
     }
   }
 
@@ -178,23 +220,23 @@ impl Building {
   }
 
   fn join_rooms(&self) {
-    // iterate through rooms
-    for room in self.rooms.values() {
-      room.join_rooms(self);
+    // iterate through rooms, in creation order so the same seed makes the same doors
+    for id in self.rooms.keys().sorted() {
+      self.rooms[id].join_rooms(self);
     }
   }
 
-  fn create_outside_doors(&self) {
-    let mut rng = thread_rng();
-    let mut count = [(0, rng.gen_range(2..5)); 4];
-    for cell in self.cells.values() {
-      cell.create_outside_door(self, &mut count);
+  fn create_outside_doors(&mut self) {
+    let mut count = [(0, self.rng.gen_range(2..5)); 4];
+    for coord in self.cells.keys().sorted() {
+      self.cells[coord].create_outside_door(self, &mut count);
     }
   }
 
   fn gen_navigation(&self) {
-    for cell in self.cells.values() {
-      cell.gen_navigation(self);
+    // in order, so the same seed numbers its nodes the same
+    for coord in self.cells.keys().sorted() {
+      self.cells[coord].gen_navigation(self);
     }
   }
 
@@ -202,15 +244,32 @@ impl Building {
     &self,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
+    cache: &mut ResMut<AssetCache>,
     materials: &mut ResMut<Assets<ZombieMaterial>>,
   ) {
-    for cell in self.cells.values() {
-      Zombie::fabricate(cell.random_pos(), commands, meshes, materials);
+    for pos in self.zombie_spawns(1.) {
+      Zombie::fabricate(pos, commands, meshes, cache, materials);
     }
   }
 
+  /// Where the building's zombies start, one in about `rate` of the cells.
+  pub fn zombie_spawns(&self, rate: f32) -> Vec<Vec3> {
+    let mut rng = StdRng::seed_from_u64(self.seed ^ Salt::Zombies as u64);
+    let rate = rate.clamp(0., 1.) as f64;
+    self
+      .cells
+      .keys()
+      .sorted()
+      .filter_map(|coord| {
+        let pos = self.cells[coord].random_pos(&mut rng);
+        rng.gen_bool(rate).then_some(pos)
+      })
+      .collect()
+  }
+
   fn seed_random_room(&mut self, arc: &Arc<Self>) {
-    let coord = match self.outer().choose(&mut thread_rng()) {
+    let outer = self.outer();
+    let coord = match outer.choose(&mut self.rng) {
       Some(coord) => *coord,
       _ => return,
     };
@@ -218,22 +277,35 @@ impl Building {
     self.seed_room(coord);
   }
 
+  /// The outside walls are all the same, picked from the seed.
+  pub fn siding(&self) -> wall::Siding {
+    let mut rng = StdRng::seed_from_u64(self.seed ^ Salt::Siding as u64);
+    *wall::Siding::ALL.choose(&mut rng).unwrap()
+  }
+
+  pub fn room(&self, id: usize) -> Option<&Arc<Room>> {
+    self.rooms.get(&id)
+  }
+
+  /// Rooms in the order they were generated in, which is the same for every seed.
+  pub fn rooms_in_order(&self) -> impl Iterator<Item = &Arc<Room>> {
+    self.rooms.keys().sorted().map(|id| &self.rooms[id])
+  }
+
+  pub fn bounds(&self) -> Option<&Rect> {
+    self.bounds.as_ref()
+  }
+
   pub fn coord_to_pos_rel(&self, coord: &Coord) -> Vec3 {
-    Vec3::new(coord.x as f32 * CELL_SIZE, 0., coord.z as f32 * CELL_SIZE)
+    CELL_GRID.center(*coord)
   }
 
   pub fn coord_to_pos_global(&self, coord: &Coord) -> Vec3 {
-    self.origin.translation + self.coord_to_pos_rel(coord)
+    CELL_GRID.local_center(&self.origin, *coord)
   }
 
   pub fn pos_global_to_coord(&self, pos: &Vec3) -> Coord {
-    let pos = *pos - self.origin.translation;
-    let z_neg = (pos.z < 0.) as i16 * -2 + 1;
-    let x_neg = (pos.x < 0.) as i16 * -2 + 1;
-    Coord {
-      z: ((pos.z.abs() + CELL_SIZE_2) / CELL_SIZE) as i16 * z_neg,
-      x: ((pos.x.abs() + CELL_SIZE_2) / CELL_SIZE) as i16 * x_neg,
-    }
+    CELL_GRID.local_coord(&self.origin, pos)
   }
 
   pub fn pos_global_to_cell(&self, pos: &Vec3) -> Option<&Arc<Cell>> {
@@ -252,11 +324,13 @@ impl Building {
         }
       }
     }
-    outer.into_iter().collect()
+    outer.into_iter().sorted().collect()
   }
 }
 
-#[derive(Debug, Default, Hash, PartialEq, Eq, Copy, Clone)]
+#[derive(
+  Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize,
+)]
 pub struct Coord {
   pub x: i16,
   pub z: i16,
@@ -300,3 +374,312 @@ impl PartialEq for Building {
   }
 }
 impl Eq for Building {}
+
+impl ConsoleCommands for Building {
+  fn register(console: &mut Console) {
+    console.command(
+      "regen",
+      "regen building",
+      "Generates the buildings around the player afresh, forgetting what changed in them",
+      Self::regen,
+    );
+  }
+}
+
+impl Building {
+  // the SystemState spells out everything the command touches, an alias would only move it
+  #[allow(clippy::type_complexity)]
+  fn regen(world: &mut World, args: &[&str]) -> Result<String, String> {
+    if args.first() != Some(&"building") {
+      return Err("Usage: regen building".into());
+    }
+
+    let mut state: SystemState<(
+      Commands,
+      ResMut<Chunks>,
+      ResMut<road::RoadGrid>,
+      ResMut<Zones>,
+      chunks::ZombieQuery,
+      Query<&Transform, With<Player>>,
+      Res<Time>,
+    )> = SystemState::new(world);
+    let (mut commands, mut chunks, mut road_grid, mut zones, zombies, player_query, time) =
+      state.get_mut(world);
+    let pos = player_query
+      .iter()
+      .next()
+      .map(|t| t.translation)
+      .ok_or("No player")?;
+    let coord = road::RoadGrid::coord(&pos);
+    chunks.regenerate(
+      coord,
+      time.seconds_since_startup(),
+      &mut commands,
+      &mut road_grid,
+      &mut zones,
+      &zombies,
+    );
+    state.apply(world);
+
+    Ok(format!("Regenerated chunk {},{}", coord.x, coord.z))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SEEDS: u64 = 2_000;
+
+  /// Every building generated from `SEEDS`, each somewhere else in the world and facing another
+  /// way, the same on every run.
+  fn buildings() -> impl Iterator<Item = Arc<Building>> {
+    (0..SEEDS).map(|seed| {
+      let mut rng = StdRng::seed_from_u64(seed);
+      let origin = Transform::from_xyz(
+        rng.gen_range(-5000.0..5000.),
+        0.,
+        rng.gen_range(-5000.0..5000.),
+      )
+      .with_rotation(Quat::from_rotation_y(
+        rng.gen_range(0.0..std::f32::consts::TAU),
+      ));
+      Building::generate(origin, seed, &[])
+    })
+  }
+
+  /// The coords reachable from `start`, stepping to an adjacent coord when `step` allows it.
+  fn flood(start: Coord, step: impl Fn(Coord, usize, Coord) -> bool) -> HashSet<Coord> {
+    let mut seen = HashSet::from([start]);
+    let mut open = vec![start];
+    while let Some(coord) = open.pop() {
+      for (i, adj) in coord.adj().into_iter().enumerate() {
+        if step(coord, i, adj) && seen.insert(adj) {
+          open.push(adj);
+        }
+      }
+    }
+    seen
+  }
+
+  #[test]
+  fn every_cell_is_reachable_through_doors() {
+    for building in buildings() {
+      let start = *building.cells.keys().min().unwrap();
+      let reached = flood(start, |coord, i, adj| {
+        building.cells.contains_key(&adj)
+          && matches!(
+            building.cells[&coord].wall_at(&building, i),
+            wall::State::None | wall::State::Door
+          )
+      });
+      assert_eq!(
+        reached.len(),
+        building.cells.len(),
+        "seed {}: cells cut off from {:?}",
+        building.seed,
+        start
+      );
+    }
+  }
+
+  #[test]
+  fn rooms_are_contiguous() {
+    for building in buildings() {
+      for room in building.rooms_in_order() {
+        let cells = room.cells.read();
+        let start = *cells.iter().min().unwrap();
+        let reached = flood(start, |_, _, adj| cells.contains(&adj));
+        assert_eq!(
+          reached.len(),
+          cells.len(),
+          "seed {}: room {} is in pieces",
+          building.seed,
+          room.id
+        );
+        for coord in cells.iter() {
+          assert_eq!(building.cells[coord].room.id, room.id);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn generators_stand_in_the_yard() {
+    for building in buildings() {
+      if building.power_source() != PowerSource::Generator {
+        continue;
+      }
+      let pos = building.generator_pos();
+      let fence = yard::Fence::around(&building.origin);
+      assert!(
+        pos.x - GENERATOR_MARGIN >= fence.min.x
+          && pos.x + GENERATOR_MARGIN <= fence.max.x
+          && pos.z - GENERATOR_MARGIN >= fence.min.z
+          && pos.z + GENERATOR_MARGIN <= fence.max.z,
+        "seed {}: generator at {} is outside the fence",
+        building.seed,
+        pos
+      );
+      for coord in building.cells.keys() {
+        let d = (pos - building.coord_to_pos_rel(coord)).abs();
+        assert!(
+          d.x.max(d.z) > CELL_SIZE_2 + GENERATOR_MARGIN,
+          "seed {}: generator at {} is inside {:?}",
+          building.seed,
+          pos,
+          coord
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn connected_to_is_symmetric() {
+    for building in buildings() {
+      for room in building.rooms_in_order() {
+        for id in room.connected_to.read().iter() {
+          let other = building
+            .room(*id)
+            .unwrap_or_else(|| panic!("seed {}: no room {}", building.seed, id));
+          assert!(
+            other.connected_to.read().contains(&room.id),
+            "seed {}: room {} connects to {} but not back",
+            building.seed,
+            room.id,
+            id
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn walls_match_on_both_sides() {
+    for building in buildings() {
+      for (coord, cell) in &building.cells {
+        let wall_state = *cell.wall_state.read();
+        for (i, adj) in coord.adj().iter().enumerate() {
+          let adj_cell = match building.cells.get(adj) {
+            Some(adj_cell) => adj_cell,
+            None => {
+              assert_ne!(
+                wall_state[i],
+                wall::State::None,
+                "seed {}: {:?} is open to the outside",
+                building.seed,
+                coord
+              );
+              continue;
+            }
+          };
+
+          let (own, other) = (wall_state[i], adj_cell.wall_state.read()[i.opposite()]);
+          match cell.room.id == adj_cell.room.id {
+            true => assert_eq!(
+              (own, other),
+              (wall::State::None, wall::State::None),
+              "seed {}: wall inside a room at {:?}",
+              building.seed,
+              coord
+            ),
+            // only one of the cells builds the wall
+            false => assert!(
+              (own == wall::State::None) != (other == wall::State::None),
+              "seed {}: wall between {:?} and {:?} is {:?} and {:?}",
+              building.seed,
+              coord,
+              adj,
+              own,
+              other
+            ),
+          }
+          assert_eq!(
+            cell.wall_at(&building, i),
+            adj_cell.wall_at(&building, i.opposite())
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn outside_doors_open_onto_empty_coords() {
+    for building in buildings() {
+      let mut doors = 0;
+      for (coord, cell) in &building.cells {
+        let nav_nodes = cell.nav_nodes.read();
+        for (i, adj) in coord.adj().iter().enumerate() {
+          if building.cells.contains_key(adj) || cell.wall_state.read()[i] != wall::State::Door {
+            continue;
+          }
+          doors += 1;
+
+          let door = nav_nodes[i].as_ref().unwrap();
+          let outside = door
+            .adj
+            .read()
+            .iter()
+            .find(|node| node.r#type == NavNodeType::Outside)
+            .cloned()
+            .unwrap_or_else(|| {
+              panic!(
+                "seed {}: door out of {:?} leads nowhere",
+                building.seed, coord
+              )
+            });
+          assert_eq!(building.pos_global_to_coord(&outside.pos), *adj);
+        }
+      }
+      assert!(doors > 0, "seed {}: no way in", building.seed);
+    }
+  }
+
+  #[test]
+  fn cells_are_inside_bounds() {
+    for building in buildings() {
+      let bounds = building.bounds().unwrap();
+      for (coord, cell) in &building.cells {
+        assert!(
+          bounds.contains(&cell.pos),
+          "seed {}: {:?} is off the property",
+          building.seed,
+          coord
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn nav_edges_go_both_ways() {
+    for building in buildings() {
+      let nodes: HashSet<Arc<NavNode>> = building
+        .cells
+        .values()
+        .flat_map(|cell| cell.nav_nodes.read().clone())
+        .flatten()
+        .collect();
+
+      for cell in building.cells.values() {
+        assert!(cell.nav_node().is_some(), "seed {}", building.seed);
+      }
+      for node in &nodes {
+        for adj in node.adj.read().iter() {
+          assert!(
+            nodes.contains(adj),
+            "seed {}: edge from {} to a node that isn't in the building",
+            building.seed,
+            node.pos
+          );
+          assert!(
+            adj.adj.read().contains(node),
+            "seed {}: edge from {} to {} is one way",
+            building.seed,
+            node.pos,
+            adj.pos
+          );
+        }
+      }
+    }
+  }
+}
@@ -1,5 +1,5 @@
-use crate::{nav::NavNodeComponent, *};
-use rand::{thread_rng, Rng};
+use crate::*;
+use rand::Rng;
 use Dir::*;
 
 #[derive(Debug)]
@@ -13,7 +13,8 @@ pub enum Dir {
 pub const CELL_SIZE: f32 = 30.;
 pub const CELL_SIZE_2: f32 = CELL_SIZE / 2.;
 pub const CARDINAL: [(i16, i16, Dir); 4] = [(0, 1, N), (1, 0, E), (0, -1, S), (-1, 0, W)];
-const WALL: [[Vec3; 2]; 4] = [
+/// The ends of each wall, relative to the cell.
+pub const WALL: [[Vec3; 2]; 4] = [
   [
     Vec3::new(CELL_SIZE_2, 0., -CELL_SIZE_2),
     Vec3::new(CELL_SIZE_2, 0., CELL_SIZE_2),
@@ -31,7 +32,8 @@ const WALL: [[Vec3; 2]; 4] = [
     Vec3::new(CELL_SIZE_2, 0., -CELL_SIZE_2),
   ],
 ];
-const WALL_NAV: [Vec3; 4] = [
+/// The middle of each wall, relative to the cell.
+pub const WALL_NAV: [Vec3; 4] = [
   Vec3::new(CELL_SIZE_2, 0., 0.),
   Vec3::new(0., 0., CELL_SIZE_2),
   Vec3::new(-CELL_SIZE_2, 0., 0.),
@@ -76,9 +78,17 @@ impl Cell {
     for (i, coord) in self.adj().iter().enumerate() {
       wall_state[i] = match building.cells.get(coord) {
         Some(cell) if cell.room.id == self.room.id => wall::State::None,
-        Some(_) if i >= 1 && i <= 2 => wall::State::Solid,
+        // the wall between rooms belongs to one of the cells, and the other may be in a room laid
+        // out earlier that walled itself off from what was empty then
+        Some(adj) if (1..=2).contains(&i) => {
+          adj.wall_state.write()[i.opposite()] = wall::State::None;
+          wall::State::Solid
+        }
+        Some(adj) => {
+          adj.wall_state.write()[i.opposite()] = wall::State::Solid;
+          wall::State::None
+        }
         None => wall::State::Solid,
-        _ => wall::State::None,
       }
     }
   }
@@ -94,16 +104,36 @@ impl Cell {
     result
   }
 
-  pub fn random_pos(&self) -> Vec3 {
-    let mut rng = thread_rng();
-    let mut pos = self.pos.clone();
-    pos.z += rng.gen_range(-CELL_SIZE_2..CELL_SIZE_2);
-    pos.x += rng.gen_range(-CELL_SIZE_2..CELL_SIZE_2);
-    pos
+  pub fn random_pos(&self, rng: &mut impl Rng) -> Vec3 {
+    let offset = Vec3::new(
+      rng.gen_range(-CELL_SIZE_2..CELL_SIZE_2),
+      0.,
+      rng.gen_range(-CELL_SIZE_2..CELL_SIZE_2),
+    );
+    self.pos + self.building.origin.rotation * offset
+  }
+
+  /// Whether there's a door in the wall on side `i`, whichever of the two cells the wall belongs to.
+  pub fn has_door(&self, building: &Building, i: usize) -> bool {
+    self.wall_at(building, i) == wall::State::Door
   }
 
-  pub fn nav_node(&self) -> Arc<NavNode> {
-    self.nav_nodes.read()[4].as_ref().unwrap().clone()
+  /// The wall on side `i`, whichever of the two cells it belongs to.
+  pub fn wall_at(&self, building: &Building, i: usize) -> wall::State {
+    let adj = building
+      .cells
+      .get(&self.adj()[i])
+      .map_or(wall::State::None, |adj| adj.wall_state.read()[i.opposite()]);
+    match (self.wall_state.read()[i], adj) {
+      (wall::State::Door, _) | (_, wall::State::Door) => wall::State::Door,
+      (wall::State::None, adj) => adj,
+      (own, _) => own,
+    }
+  }
+
+  /// Only missing before navigation has been generated.
+  pub fn nav_node(&self) -> Option<Arc<NavNode>> {
+    self.nav_nodes.read()[4].clone()
   }
 
   pub fn create_door(&self, other: &Cell, cardinal_dir: usize) {
@@ -129,22 +159,6 @@ impl Cell {
     }
   }
 
-  pub fn fabricate_nav(
-    &self,
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-  ) {
-    for nav_node in &*self.nav_nodes.read() {
-      if let Some(nav_node) = nav_node {
-        NavNodeComponent {
-          node: nav_node.clone(),
-        }
-        .fabricate(commands, meshes, materials);
-      }
-    }
-  }
-
   /// returns a list of adjacent coordinates that are blank
   pub fn adj_empty(&self, building: &Building) -> Vec<Coord> {
     self
@@ -164,8 +178,18 @@ pub trait ArcCellExt {
     child_builder: &mut ChildBuilder,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
     asset_server: &Res<AssetServer>,
   ) -> Entity;
+  /// Adds the floor and walls to a building being baked, instead of spawning them.
+  fn bake(
+    &self,
+    building: &Building,
+    baker: &mut Baker,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    asset_server: &Res<AssetServer>,
+  );
 }
 
 impl ArcCellExt for ArcCell {
@@ -174,7 +198,10 @@ impl ArcCellExt for ArcCell {
     let wall_state = self.wall_state.read();
 
     let pos = building.coord_to_pos_global(&self.coord);
-    let area = Rect::build(CELL_SIZE, CELL_SIZE).center_at(&pos);
+    let rotation = building.origin.rotation;
+    let area = Rect::build(CELL_SIZE, CELL_SIZE)
+      .center_at(&pos)
+      .rotated_about(pos, rotation);
     let mut nav_nodes = self.nav_nodes.write();
     let cell_nav = NavNode::new(pos, NavNodeType::Cell, area, HashSet::new());
 
@@ -183,8 +210,10 @@ impl ArcCellExt for ArcCell {
 
       match state {
         wall::State::Door => {
-          let pos = pos + WALL_NAV[i];
-          let area = Rect::build(wall::DOOR_W_2, wall::DOOR_W_2).center_at(&pos);
+          let pos = pos + rotation * WALL_NAV[i];
+          let area = Rect::build(wall::DOOR_W_2, wall::DOOR_W_2)
+            .center_at(&pos)
+            .rotated_about(pos, rotation);
           let door_nav = NavNode::new(
             pos,
             NavNodeType::Door,
@@ -200,10 +229,12 @@ impl ArcCellExt for ArcCell {
             }
           }
 
-          // outside...
+          // outside, in the middle of the coord the door opens onto...
           if adj_cell.is_none() {
-            let pos = pos + (WALL_NAV[i] * 2.);
-            let area = Rect::build(wall::DOOR_W_2, wall::DOOR_W_2).center_at(&pos);
+            let pos = building.coord_to_pos_global(&adj[i]);
+            let area = Rect::build(wall::DOOR_W_2, wall::DOOR_W_2)
+              .center_at(&pos)
+              .rotated_about(pos, rotation);
             let outside_nav = NavNode::new(
               pos,
               NavNodeType::Outside,
@@ -211,15 +242,13 @@ impl ArcCellExt for ArcCell {
               HashSet::from([door_nav.clone()]),
             );
 
-            // link the outside back to the door
-            door_nav.adj.write().insert(door_nav.clone());
+            // link the door out to the outside
+            door_nav.adj.write().insert(outside_nav.clone());
 
-            nav_nodes[5] = Some(outside_nav.clone());
-            let _ = ZONE_TX.send(ZItem::Nav(outside_nav));
+            nav_nodes[5] = Some(outside_nav);
           }
 
-          nav_nodes[i] = Some(door_nav.clone());
-          let _ = ZONE_TX.send(ZItem::Nav(door_nav));
+          nav_nodes[i] = Some(door_nav);
         }
         wall::State::None => {
           // if there is a cell in this direction...
@@ -244,8 +273,7 @@ impl ArcCellExt for ArcCell {
       }
     }
 
-    nav_nodes[4] = Some(cell_nav.clone());
-    let _ = ZONE_TX.send(ZItem::Nav(cell_nav));
+    nav_nodes[4] = Some(cell_nav);
   }
 
   fn fabricate(
@@ -254,21 +282,22 @@ impl ArcCellExt for ArcCell {
     child_builder: &mut ChildBuilder,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
     asset_server: &Res<AssetServer>,
   ) -> Entity {
-    let material = self.room.floor_mat();
+    let material = self.room.floor_mat(materials, cache, asset_server);
 
     let translation = building.coord_to_pos_rel(&self.coord);
     let transform = Transform::from_translation(translation);
 
     // println!("Floor transform: {:?}", &transform);
 
-    let mesh = Mesh::from(shape::Plane { size: CELL_SIZE });
+    let mesh = cache.mesh(meshes, MeshKey::plane(CELL_SIZE));
     let collider = Collider::cuboid(CELL_SIZE / 2., 0.1, CELL_SIZE / 2.);
 
     child_builder
       .spawn_bundle(PbrBundle {
-        mesh: meshes.add(mesh),
+        mesh,
         material,
         transform,
         ..default()
@@ -276,21 +305,60 @@ impl ArcCellExt for ArcCell {
       .insert(collider)
       .insert(CellComponent { cell: self.clone() })
       .with_children(|child_builder| {
-        let wall_state = self.wall_state.read();
-        for i in 0..4 {
-          let w = WALL[i];
-          Wall::build(w[0], w[1], wall_state[i]).fabricate_as_child(
-            child_builder,
-            meshes,
-            materials,
-          );
+        for wall in self.walls(building) {
+          wall.fabricate_as_child(child_builder, meshes, materials, cache, asset_server);
         }
       })
       .id()
   }
+
+  fn bake(
+    &self,
+    building: &Building,
+    baker: &mut Baker,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    asset_server: &Res<AssetServer>,
+  ) {
+    let transform = Transform::from_translation(building.coord_to_pos_rel(&self.coord));
+
+    baker.add(Part::Mesh {
+      key: MeshKey::plane(CELL_SIZE),
+      material: self.room.floor_mat(materials, cache, asset_server),
+      transform,
+    });
+    baker.add(Part::Collider {
+      collider: Collider::cuboid(CELL_SIZE / 2., 0.1, CELL_SIZE / 2.),
+      transform,
+    });
+
+    for wall in self.walls(building) {
+      wall.bake(&transform, baker, materials, cache, asset_server);
+    }
+  }
+}
+
+impl Cell {
+  fn walls(&self, building: &Building) -> Vec<Wall> {
+    let wall_state = self.wall_state.read();
+    self
+      .adj()
+      .iter()
+      .enumerate()
+      .map(|(i, adj)| {
+        let w = WALL[i];
+        // walls between rooms are only built by one of the cells, so show both rooms
+        let outer = match building.cells.get(adj) {
+          Some(adj_cell) => adj_cell.room.wallpaper,
+          None => wall::Face::Siding(building.siding()),
+        };
+        Wall::build(w[0], w[1], wall_state[i]).with_faces(self.room.wallpaper, outer)
+      })
+      .collect()
+  }
 }
 
-trait DoorIndex {
+pub(crate) trait DoorIndex {
   fn opposite(&self) -> usize;
 }
 impl DoorIndex for usize {
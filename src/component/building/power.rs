@@ -0,0 +1,324 @@
+use super::cell::WALL_NAV;
+use crate::*;
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// How close the player has to be to flip a switch.
+const INTERACT_RANGE: f32 = 8.;
+
+const LIGHT_HEIGHT: f32 = CELL_SIZE_2 - 1.;
+const LIGHT_INTENSITY: f32 = 4000.;
+const SWITCH_HEIGHT: f32 = 6.;
+const SWITCH_SIZE: f32 = 0.8;
+/// How far from the middle of a door its light switch is.
+const SWITCH_OFFSET: f32 = wall::DOOR_W_2 + 2.;
+/// How far toward a wall things on it sit, as a fraction of the way from the middle of the cell.
+const WALL_INSET: f32 = 0.9;
+const GENERATOR_SIZE: Vec3 = Vec3::new(6., 4., 4.);
+/// Gap between the back of the building and its generator.
+const GENERATOR_GAP: f32 = 10.;
+/// How far the middle of the generator stays inside the fence.
+pub(super) const GENERATOR_MARGIN: f32 = GENERATOR_SIZE.x / 2. + 2.;
+
+/// Where a building gets its power from, picked from its seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+  /// On a wall inside.
+  FuseBox,
+  /// Out in the back yard.
+  Generator,
+}
+
+/// Power and light switches of a building, for saving.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PowerState {
+  pub powered: bool,
+  /// Rooms with their light switched off, by the order they were generated in.
+  pub rooms_off: Vec<usize>,
+}
+
+/// The ceiling light of a room.
+#[derive(Component)]
+pub struct RoomLight {
+  room: Arc<Room>,
+}
+
+#[derive(Component)]
+pub struct LightSwitch {
+  room: Arc<Room>,
+}
+
+/// The fuse box or generator of a building.
+#[derive(Component)]
+pub struct PowerSwitch {
+  building: Arc<Building>,
+}
+
+impl Building {
+  pub fn is_powered(&self) -> bool {
+    self.powered.load(Ordering::Relaxed)
+  }
+
+  pub fn power_source(&self) -> PowerSource {
+    match StdRng::seed_from_u64(self.seed ^ Salt::Power as u64).gen_bool(0.5) {
+      true => PowerSource::FuseBox,
+      false => PowerSource::Generator,
+    }
+  }
+
+  /// Where a generator goes relative to the building: behind it, which is along +x, but inside the
+  /// fenced yard.
+  pub fn generator_pos(&self) -> Vec3 {
+    let back = self.cells.keys().map(|coord| coord.x).max().unwrap_or(0);
+    let pos =
+      self.coord_to_pos_rel(&Coord { x: back, z: 0 }) + Vec3::X * (CELL_SIZE_2 + GENERATOR_GAP);
+    super::yard::Fence::around(&self.origin).clamp(pos, GENERATOR_MARGIN)
+  }
+
+  pub fn power_state(&self) -> PowerState {
+    PowerState {
+      powered: self.is_powered(),
+      rooms_off: self
+        .rooms_in_order()
+        .positions(|room| !room.switched_on.load(Ordering::Relaxed))
+        .collect(),
+    }
+  }
+
+  pub fn restore_power(&self, state: &PowerState) {
+    self.powered.store(state.powered, Ordering::Relaxed);
+    for (i, room) in self.rooms_in_order().enumerate() {
+      room
+        .switched_on
+        .store(!state.rooms_off.contains(&i), Ordering::Relaxed);
+    }
+  }
+
+  /// Ceiling lights and switches for every room, and the building's power source.
+  pub fn fabricate_power(
+    &self,
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+  ) {
+    let switch_mesh = cache.mesh(meshes, MeshKey::cube(SWITCH_SIZE));
+    let switch_material = cache.material(materials, "power/switch", || StandardMaterial {
+      base_color: Color::rgb(0.9, 0.9, 0.85),
+      ..default()
+    });
+
+    for room in self.rooms_in_order() {
+      let cells = room
+        .cells
+        .read()
+        .iter()
+        .copied()
+        .sorted()
+        .collect::<Vec<_>>();
+      if cells.is_empty() {
+        continue;
+      }
+
+      let center = cells
+        .iter()
+        .fold(Vec3::ZERO, |sum, coord| sum + self.coord_to_pos_rel(coord))
+        / cells.len() as f32;
+      child_builder
+        .spawn_bundle(PointLightBundle {
+          point_light: PointLight {
+            intensity: LIGHT_INTENSITY,
+            range: CELL_SIZE * (1. + (cells.len() as f32).sqrt()),
+            ..default()
+          },
+          transform: Transform::from_translation(center + Vec3::Y * LIGHT_HEIGHT),
+          visibility: Visibility { is_visible: false },
+          ..default()
+        })
+        .insert(RoomLight { room: room.clone() });
+
+      // next to the first door into the room
+      let door = cells
+        .iter()
+        .filter_map(|coord| self.cells.get(coord))
+        .find_map(|cell| (0..4).find(|i| cell.has_door(self, *i)).map(|i| (cell, i)));
+      if let Some((cell, i)) = door {
+        let along = Vec3::Y.cross(WALL_NAV[i]).normalize();
+        let pos = self.coord_to_pos_rel(&cell.coord)
+          + WALL_NAV[i] * WALL_INSET
+          + along * SWITCH_OFFSET
+          + Vec3::Y * SWITCH_HEIGHT;
+        child_builder
+          .spawn_bundle(PbrBundle {
+            mesh: switch_mesh.clone(),
+            material: switch_material.clone(),
+            transform: Transform::from_translation(pos),
+            ..default()
+          })
+          .insert(LightSwitch { room: room.clone() });
+      }
+    }
+
+    self.fabricate_power_source(child_builder, meshes, materials, cache);
+  }
+
+  fn fabricate_power_source(
+    &self,
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+  ) {
+    let material = cache.material(materials, "power/source", || StandardMaterial {
+      base_color: Color::rgb(0.55, 0.55, 0.5),
+      ..default()
+    });
+    let mut rng = StdRng::seed_from_u64(self.seed ^ Salt::PowerPlacement as u64);
+
+    let (mesh, translation, collider) = match self.power_source() {
+      PowerSource::FuseBox => {
+        // on any solid wall
+        let walls = self
+          .cells
+          .keys()
+          .sorted()
+          .flat_map(|coord| {
+            let cell = &self.cells[coord];
+            let wall_state = cell.wall_state.read();
+            (0..4)
+              .filter(|i| wall_state[*i] == wall::State::Solid)
+              .map(|i| (*coord, i))
+              .collect::<Vec<_>>()
+          })
+          .collect::<Vec<_>>();
+        let (coord, i) = match walls.choose(&mut rng) {
+          Some(wall) => *wall,
+          None => return,
+        };
+        let pos = self.coord_to_pos_rel(&coord) + WALL_NAV[i] * WALL_INSET;
+        (
+          cache.mesh(meshes, MeshKey::cuboid(2., 3., 2.)),
+          pos + Vec3::Y * SWITCH_HEIGHT,
+          None,
+        )
+      }
+      PowerSource::Generator => {
+        let pos = self.generator_pos();
+        (
+          cache.mesh(
+            meshes,
+            MeshKey::cuboid(GENERATOR_SIZE.x, GENERATOR_SIZE.y, GENERATOR_SIZE.z),
+          ),
+          pos + Vec3::Y * GENERATOR_SIZE.y / 2.,
+          Some(Collider::cuboid(
+            GENERATOR_SIZE.x / 2.,
+            GENERATOR_SIZE.y / 2.,
+            GENERATOR_SIZE.z / 2.,
+          )),
+        )
+      }
+    };
+
+    let mut source = child_builder.spawn_bundle(PbrBundle {
+      mesh,
+      material,
+      transform: Transform::from_translation(translation),
+      ..default()
+    });
+    source.insert(PowerSwitch {
+      building: self.arc(),
+    });
+    if let Some(collider) = collider {
+      source.insert(collider);
+    }
+  }
+}
+
+/// A light switch, fuse box or generator the player can reach.
+pub enum Switch<'a> {
+  Light(&'a LightSwitch),
+  Power(&'a PowerSwitch),
+}
+
+impl<'a> Switch<'a> {
+  /// The closest one within `INTERACT_RANGE` of `pos` on the ground.
+  pub fn nearest(
+    pos: Vec3,
+    lights: impl Iterator<Item = (&'a GlobalTransform, &'a LightSwitch)>,
+    sources: impl Iterator<Item = (&'a GlobalTransform, &'a PowerSwitch)>,
+  ) -> Option<Self> {
+    let distance = |t: &GlobalTransform| {
+      let d = t.translation() - pos;
+      Vec3::new(d.x, 0., d.z).length()
+    };
+
+    lights
+      .map(|(t, switch)| (distance(t), Self::Light(switch)))
+      .chain(sources.map(|(t, source)| (distance(t), Self::Power(source))))
+      .filter(|(d, _)| *d <= INTERACT_RANGE)
+      .min_by(|a, b| a.0.total_cmp(&b.0))
+      .map(|(_, switch)| switch)
+  }
+
+  pub fn flip(&self) {
+    let on = match self {
+      Self::Light(switch) => &switch.room.switched_on,
+      Self::Power(source) => &source.building.powered,
+    };
+    on.store(!on.load(Ordering::Relaxed), Ordering::Relaxed);
+  }
+
+  /// What flipping it would do, for prompting the player.
+  pub fn describe(&self) -> String {
+    match self {
+      Self::Light(switch) => match switch.room.switched_on.load(Ordering::Relaxed) {
+        true => "Turn the lights off".into(),
+        false => "Turn the lights on".into(),
+      },
+      Self::Power(source) => {
+        let source_name = match source.building.power_source() {
+          PowerSource::FuseBox => "fuse box",
+          PowerSource::Generator => "generator",
+        };
+        match source.building.is_powered() {
+          true => format!("Turn the power off at the {}", source_name),
+          false => format!("Turn the power on at the {}", source_name),
+        }
+      }
+    }
+  }
+}
+
+impl LightSwitch {
+  /// Flips the closest light switch, fuse box or generator in reach of the player.
+  pub fn interact(
+    actions: Res<Actions>,
+    player_query: Query<&Transform, With<Player>>,
+    switch_query: Query<(&GlobalTransform, &LightSwitch)>,
+    source_query: Query<(&GlobalTransform, &PowerSwitch)>,
+  ) {
+    if !actions.just_pressed(Action::Interact) {
+      return;
+    }
+    let player = match player_query.iter().next() {
+      Some(t) => t.translation,
+      None => return,
+    };
+
+    if let Some(switch) = Switch::nearest(player, switch_query.iter(), source_query.iter()) {
+      switch.flip();
+    }
+  }
+}
+
+impl RoomLight {
+  pub fn update(mut query: Query<(&Self, &mut Visibility)>) {
+    for (light, mut visibility) in &mut query {
+      let lit = light.room.is_lit();
+      if visibility.is_visible != lit {
+        visibility.is_visible = lit;
+      }
+    }
+  }
+}
@@ -0,0 +1,193 @@
+use super::wall::WALL_H;
+use crate::*;
+use itertools::Itertools;
+
+const ROOF_T: f32 = 1.;
+const ROOF_COLOR: Color = Color::rgb(0.25, 0.22, 0.22);
+/// Seconds the roof takes to fade in or out.
+const ROOF_FADE: f32 = 0.4;
+/// Just above the walls, so covers hide them too.
+const COVER_HEIGHT: f32 = WALL_H + 0.05;
+/// How dark rooms that have been seen, but aren't in view, are.
+const REMEMBERED_ALPHA: f32 = 0.6;
+
+/// Covers the whole building, fading out while the player is inside it.
+#[derive(Component)]
+pub struct Roof {
+  building: Arc<Building>,
+  /// Only this building's roof uses it, since every roof fades on its own.
+  material: Handle<StandardMaterial>,
+  /// 0 to 1, how opaque the roof is.
+  alpha: f32,
+}
+
+/// Hides a cell of a room that isn't in view, fully if it has never been seen.
+#[derive(Component)]
+pub struct FogCover {
+  room: Arc<Room>,
+}
+
+impl Building {
+  /// Rooms the player has seen into, by the order they were generated in, for saving.
+  pub fn seen_rooms(&self) -> Vec<usize> {
+    self
+      .rooms_in_order()
+      .positions(|room| room.seen.load(Ordering::Relaxed))
+      .collect()
+  }
+
+  pub fn restore_seen_rooms(&self, seen: &[usize]) {
+    for (i, room) in self.rooms_in_order().enumerate() {
+      room.seen.store(seen.contains(&i), Ordering::Relaxed);
+    }
+  }
+
+  /// The roof, and a fog cover over every cell under it. With `bake`, the roof is merged into one
+  /// mesh. The covers change with the rooms they hide, so they never are.
+  pub fn fabricate_roof(
+    &self,
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    bake: bool,
+  ) {
+    let material = materials.add(StandardMaterial {
+      base_color: ROOF_COLOR,
+      alpha_mode: AlphaMode::Blend,
+      perceptual_roughness: 0.9,
+      ..default()
+    });
+    let parts = self
+      .cells
+      .keys()
+      .map(|coord| Part::Mesh {
+        key: MeshKey::cuboid(CELL_SIZE, ROOF_T, CELL_SIZE),
+        material: material.clone(),
+        transform: Transform::from_translation(
+          self.coord_to_pos_rel(coord) + Vec3::Y * (WALL_H + ROOF_T / 2.),
+        ),
+      })
+      .collect::<Vec<_>>();
+
+    child_builder
+      .spawn_bundle(SpatialBundle::default())
+      .insert(Roof {
+        building: self.arc(),
+        material,
+        alpha: 1.,
+      })
+      .with_children(|child_builder| {
+        if bake {
+          let mut baker = Baker::default();
+          for part in parts {
+            baker.add(part);
+          }
+          baker.fabricate(child_builder, meshes);
+        } else {
+          for part in parts {
+            part.fabricate(child_builder, meshes, cache);
+          }
+        }
+      });
+
+    let cover_mesh = cache.mesh(meshes, MeshKey::plane(CELL_SIZE));
+    let unseen = FogCover::unseen_material(materials, cache);
+    for cell in self.cells.values() {
+      child_builder
+        .spawn_bundle(PbrBundle {
+          mesh: cover_mesh.clone(),
+          material: unseen.clone(),
+          transform: Transform::from_translation(
+            self.coord_to_pos_rel(&cell.coord) + Vec3::Y * COVER_HEIGHT,
+          ),
+          ..default()
+        })
+        .insert(FogCover {
+          room: cell.room.clone(),
+        });
+    }
+  }
+}
+
+impl Roof {
+  /// Fades the roof of the building the player is in out, and every other roof back in.
+  pub fn update(
+    time: Res<Time>,
+    fog_of_war: Res<FogOfWar>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<(&mut Self, &mut Visibility)>,
+  ) {
+    let step = time.delta_seconds() / ROOF_FADE;
+    for (mut roof, mut visibility) in &mut query {
+      let inside = fog_of_war
+        .building
+        .as_ref()
+        .is_some_and(|building| Arc::ptr_eq(building, &roof.building));
+      let alpha = match inside {
+        true => (roof.alpha - step).max(0.),
+        false => (roof.alpha + step).min(1.),
+      };
+      if alpha == roof.alpha {
+        continue;
+      }
+
+      roof.alpha = alpha;
+      visibility.is_visible = alpha > 0.;
+      if let Some(material) = materials.get_mut(&roof.material) {
+        material.base_color.set_a(alpha);
+      }
+    }
+  }
+}
+
+impl FogCover {
+  fn unseen_material(
+    materials: &mut Assets<StandardMaterial>,
+    cache: &mut AssetCache,
+  ) -> Handle<StandardMaterial> {
+    cache.material(materials, "fog/unseen", || StandardMaterial {
+      base_color: Color::BLACK,
+      unlit: true,
+      ..default()
+    })
+  }
+
+  fn remembered_material(
+    materials: &mut Assets<StandardMaterial>,
+    cache: &mut AssetCache,
+  ) -> Handle<StandardMaterial> {
+    cache.material(materials, "fog/remembered", || StandardMaterial {
+      base_color: Color::rgba(0., 0., 0., REMEMBERED_ALPHA),
+      alpha_mode: AlphaMode::Blend,
+      unlit: true,
+      ..default()
+    })
+  }
+
+  /// Clears the covers of rooms in view, and dims the ones of rooms that have been seen before.
+  pub fn update(
+    fog_of_war: Res<FogOfWar>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cache: ResMut<AssetCache>,
+    mut query: Query<(&Self, &mut Handle<StandardMaterial>, &mut Visibility)>,
+  ) {
+    let unseen = Self::unseen_material(&mut materials, &mut cache);
+    let remembered = Self::remembered_material(&mut materials, &mut cache);
+
+    for (cover, mut material, mut visibility) in &mut query {
+      let in_view = fog_of_war.in_view.contains(&cover.room.id);
+      if visibility.is_visible == in_view {
+        visibility.is_visible = !in_view;
+      }
+
+      let wanted = match cover.room.seen.load(Ordering::Relaxed) {
+        true => &remembered,
+        false => &unseen,
+      };
+      if *material != *wanted {
+        *material = wanted.clone();
+      }
+    }
+  }
+}
@@ -1,18 +1,14 @@
 use super::Coord;
 use crate::building::Cell;
 use crate::*;
-use crossbeam_channel::bounded;
-use rand::{distributions::Standard, prelude::Distribution, seq::SliceRandom, thread_rng, Rng};
+use itertools::Itertools;
+use rand::{
+  distributions::Standard, prelude::Distribution, rngs::StdRng, seq::SliceRandom, Rng, SeedableRng,
+};
 
 pub const MAX_SIZE: usize = 8;
 static ROOM_COUNT: AtomicUsize = AtomicUsize::new(1);
 
-type FloorMatHash = HashMap<RoomType, Handle<StandardMaterial>>;
-lazy_static! {
-  static ref FMC: (Sender<FloorMatHash>, Receiver<FloorMatHash>) = bounded(1);
-  static ref FLOOR_MAT: FloorMatHash = FMC.1.recv().unwrap();
-}
-
 #[derive(Debug)]
 pub struct Room {
   pub id: usize,
@@ -21,6 +17,11 @@ pub struct Room {
   building: Arc<Building>,
   size: usize,
   r#type: RoomType,
+  pub wallpaper: wall::Face,
+  /// Whether the light switch is on. The light only comes on if the building has power too.
+  pub switched_on: AtomicBool,
+  /// Whether the player has ever seen into the room. Rooms they haven't stay hidden.
+  pub seen: AtomicBool,
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
@@ -39,14 +40,21 @@ impl Distribution<RoomType> for Standard {
 }
 
 impl Room {
-  fn new(building: Arc<Building>) -> Arc<Self> {
+  fn new(building: &mut Building) -> Arc<Self> {
+    // its own rng, so picking wallpaper doesn't change the layout of a seed
+    let mut wallpaper_rng = StdRng::seed_from_u64(
+      (building.seed ^ Salt::Wallpaper as u64).wrapping_add(building.rooms.len() as u64),
+    );
     Arc::new(Self {
       id: ROOM_COUNT.fetch_add(1, Ordering::SeqCst),
       cells: RwLock::default(),
       connected_to: RwLock::default(),
-      size: thread_rng().gen_range(0..MAX_SIZE) + 2,
-      r#type: rand::random(),
-      building: building,
+      size: building.rng.gen_range(0..MAX_SIZE) + 2,
+      r#type: building.rng.gen(),
+      building: building.arc(),
+      wallpaper: wall::Face::random_wallpaper(&mut wallpaper_rng),
+      switched_on: AtomicBool::new(true),
+      seen: AtomicBool::new(false),
     })
   }
 
@@ -57,32 +65,33 @@ impl Room {
     self.cells.read().is_empty()
   }
 
-  pub fn floor_mat(&self) -> Handle<StandardMaterial> {
-    FLOOR_MAT.get(&self.r#type).unwrap().clone()
+  pub fn is_lit(&self) -> bool {
+    self.switched_on.load(Ordering::Relaxed) && self.building.is_powered()
   }
 
-  pub fn load_materials(ass: Res<AssetServer>, mut mat: ResMut<Assets<StandardMaterial>>) {
-    let mut floor_mat = HashMap::new();
-    let texture = ass.load("room/floor/wood_floor_2x.jpg");
-    floor_mat.insert(
-      RoomType::Bedroom,
-      mat.add(StandardMaterial {
-        base_color_texture: Some(texture),
-        alpha_mode: AlphaMode::Blend,
-        ..default()
-      }),
-    );
-    let texture = ass.load("room/floor/kitchen.jpg");
-    floor_mat.insert(
-      RoomType::Kitchen,
-      mat.add(StandardMaterial {
-        base_color_texture: Some(texture),
-        alpha_mode: AlphaMode::Blend,
-        ..default()
-      }),
-    );
+  /// What kind of room it is, for showing the player.
+  pub fn name(&self) -> &'static str {
+    match self.r#type {
+      RoomType::Bedroom => "Bedroom",
+      RoomType::Kitchen => "Kitchen",
+    }
+  }
 
-    let _ = FMC.0.send(floor_mat);
+  pub fn floor_mat(
+    &self,
+    materials: &mut Assets<StandardMaterial>,
+    cache: &mut AssetCache,
+    ass: &AssetServer,
+  ) -> Handle<StandardMaterial> {
+    let path = match self.r#type {
+      RoomType::Bedroom => "room/floor/wood_floor_2x.jpg",
+      RoomType::Kitchen => "room/floor/kitchen.jpg",
+    };
+    cache.material(materials, path, || StandardMaterial {
+      base_color_texture: Some(ass.load(path)),
+      alpha_mode: AlphaMode::Blend,
+      ..default()
+    })
   }
 }
 
@@ -94,9 +103,9 @@ pub trait ArcRoomExt {
 
 impl ArcRoomExt for ArcRoom {
   fn join_rooms(&self, building: &Building) {
-    let cells = &*self.cells.read();
+    let cells: Vec<Coord> = self.cells.read().iter().copied().sorted().collect();
 
-    for cell in cells {
+    for cell in &cells {
       for (i, adj_coord) in cell.adj().iter().enumerate() {
         if let Some(adj_cell) = building.cells.get(adj_coord) {
           let mut connected_to = self.connected_to.write();
@@ -117,7 +126,7 @@ impl ArcRoomExt for ArcRoom {
   }
 
   fn create(building: &mut Building, start_coord: Coord) -> Self {
-    let room = Room::new(building.arc());
+    let room = Room::new(building);
 
     while room.len() < room.size {
       // get empty adj coords
@@ -128,9 +137,10 @@ impl ArcRoomExt for ArcRoom {
         empty_coords.extend(adj);
       }
 
-      let empty_coords: Vec<Coord> = empty_coords.into_iter().collect();
+      // sorted so the same seed always picks the same coords
+      let empty_coords: Vec<Coord> = empty_coords.into_iter().sorted().collect();
 
-      let coord = match empty_coords.choose(&mut thread_rng()) {
+      let coord = match empty_coords.choose(&mut building.rng) {
         Some(coord) => *coord,
         None if room.is_empty() => start_coord,
         None => {
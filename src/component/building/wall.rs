@@ -1,22 +1,94 @@
 use super::cell::CELL_SIZE;
 use crate::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 const WALL_W: f32 = 0.5;
 const WALL_W_2: f32 = WALL_W / 2.;
 pub const DOOR_W: f32 = 8.;
 pub const DOOR_W_2: f32 = DOOR_W / 2.;
-const WALL_H: f32 = CELL_SIZE / 2.;
+pub const WALL_H: f32 = CELL_SIZE / 2.;
 const WALL_H_2: f32 = WALL_H / 2.;
 const WALL_H_4: f32 = WALL_H_2 / 2.;
 const WALL_H_8: f32 = WALL_H_4 / 2.;
 const FRAME_W: f32 = WALL_W * 2.;
 
+/// Tints for `room/wall.jpg`, so rooms next to each other can look different.
+const WALLPAPERS: [Color; 6] = [
+  Color::WHITE,
+  Color::rgb(0.96, 0.9, 0.78),
+  Color::rgb(0.8, 0.88, 0.94),
+  Color::rgb(0.84, 0.92, 0.8),
+  Color::rgb(0.95, 0.82, 0.82),
+  Color::rgb(0.86, 0.82, 0.92),
+];
+
 #[derive(Component)]
 pub struct Wall {
   len: f32,
   state: State,
   translation: Vec3,
   rotation: Quat,
+  /// Which way along the wall's local x the inner face is, towards the center of its cell.
+  inner_side: f32,
+  /// Inner and outer face. Plain white when not given.
+  faces: Option<[Face; 2]>,
+}
+
+/// What one side of a wall looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+  Siding(Siding),
+  /// An index into `WALLPAPERS`.
+  Wallpaper(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Siding {
+  Brick,
+  VinylBeige,
+}
+
+impl Siding {
+  pub const ALL: [Self; 2] = [Self::Brick, Self::VinylBeige];
+}
+
+impl Face {
+  pub fn random_wallpaper(rng: &mut impl Rng) -> Self {
+    Self::Wallpaper(rng.gen_range(0..WALLPAPERS.len()))
+  }
+
+  /// Shared between every wall with the same face.
+  pub fn material(
+    self,
+    cache: &mut ResMut<AssetCache>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    ass: &Res<AssetServer>,
+  ) -> Handle<StandardMaterial> {
+    let (key, path, base_color) = match self {
+      Self::Siding(Siding::Brick) => (
+        "siding/brick".to_string(),
+        "texture/building/siding/brick.jpg",
+        Color::WHITE,
+      ),
+      Self::Siding(Siding::VinylBeige) => (
+        "siding/vinyl-beige".to_string(),
+        "texture/building/siding/vinyl-beige.jpg",
+        Color::WHITE,
+      ),
+      Self::Wallpaper(i) => (
+        format!("wallpaper/{}", i),
+        "texture/building/room/wall.jpg",
+        WALLPAPERS[i % WALLPAPERS.len()],
+      ),
+    };
+    cache.material(materials, &key, || StandardMaterial {
+      base_color,
+      base_color_texture: Some(ass.load(path)),
+      perceptual_roughness: 1.,
+      ..default()
+    })
+  }
 }
 
 impl Wall {
@@ -26,29 +98,48 @@ impl Wall {
     let angle = (dx / dz).atan();
     let len = (dz * dz + dx * dx).sqrt();
 
+    let translation = Vec3::new((from.x + to.x) / 2., 0., (from.z + to.z) / 2.);
+    let rotation = Quat::from_axis_angle(Vec3::Y, angle);
+    let inner_side = match (rotation.inverse() * -translation).x < 0. {
+      true => -1.,
+      false => 1.,
+    };
+
     Self {
       len,
       state,
-      translation: Vec3::new((from.x + to.x) / 2., 0., (from.z + to.z) / 2.),
-      rotation: Quat::from_axis_angle(Vec3::Y, angle),
+      translation,
+      rotation,
+      inner_side,
+      faces: None,
     }
   }
 
+  /// `inner` faces the center of the cell the wall was built around, `outer` away from it.
+  pub fn with_faces(mut self, inner: Face, outer: Face) -> Self {
+    self.faces = Some([inner, outer]);
+    self
+  }
+
   pub fn fabricate(
     self,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
   ) -> Option<Entity> {
-    self._fabricate(commands.spawn(), meshes, materials)
+    self._fabricate(commands.spawn(), meshes, materials, cache, ass)
   }
   pub fn fabricate_as_child(
     self,
     child_builder: &mut ChildBuilder,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
   ) -> Option<Entity> {
-    self._fabricate(child_builder.spawn(), meshes, materials)
+    self._fabricate(child_builder.spawn(), meshes, materials, cache, ass)
   }
 
   fn _fabricate(
@@ -56,145 +147,168 @@ impl Wall {
     mut ec: EntityCommands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
   ) -> Option<Entity> {
+    let parts = self.parts(materials, cache, ass);
+    if parts.is_empty() {
+      return None;
+    }
+
     let ec = ec.insert_bundle(PbrBundle {
-      transform: Transform::from_translation(self.translation).with_rotation(self.rotation),
+      transform: self.transform(),
       ..default()
     });
-
-    match self.state {
-      State::Solid => self.fabricate_wall(ec, meshes, materials),
-      State::Door => self.fabricate_door(ec, meshes, materials),
-      _ => return None,
-    };
+    ec.with_children(|child_builder| {
+      for part in parts {
+        part.fabricate(child_builder, meshes, cache);
+      }
+    });
 
     Some(ec.insert(self).id())
   }
 
-  fn fabricate_wall(
-    &self,
-    ec: &mut EntityCommands,
-    meshes: &mut ResMut<Assets<Mesh>>,
+  /// Adds the wall to a building being baked, where `parent` is where the wall was built around.
+  pub fn bake(
+    self,
+    parent: &Transform,
+    baker: &mut Baker,
     materials: &mut ResMut<Assets<StandardMaterial>>,
-  ) -> Entity {
-    let mesh = meshes.add(Mesh::from(shape::Box::new(WALL_W, WALL_H, self.len)));
-    let collider = Collider::cuboid(WALL_W, WALL_H_2, self.len / 2.);
-    let material = materials.add(Self::white_material());
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
+  ) {
+    let transform = parent.mul_transform(self.transform());
+    for part in self.parts(materials, cache, ass) {
+      baker.add(part.transformed(&transform));
+    }
+  }
 
-    ec.with_children(|child_builder| {
-      child_builder
-        .spawn_bundle(PbrBundle {
-          mesh,
-          material,
-          transform: Transform::from_translation(Vec3::new(0., WALL_H_2, 0.)),
-          ..default()
-        })
-        .insert(collider);
-    })
-    .id()
+  fn transform(&self) -> Transform {
+    Transform::from_translation(self.translation).with_rotation(self.rotation)
   }
 
-  fn fabricate_door(
+  /// What the wall is made of, relative to the wall.
+  fn parts(
     &self,
-    ec: &mut EntityCommands,
-    meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
-  ) -> Entity {
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
+  ) -> Vec<Part> {
+    let faces = match self.faces {
+      Some(faces) => faces.map(|face| face.material(cache, materials, ass)),
+      None => {
+        let white = cache.material(materials, "wall/white", Self::white_material);
+        [white.clone(), white]
+      }
+    };
+
+    let mut parts = vec![];
+    match self.state {
+      State::Solid => self.wall_parts(&faces, &mut parts),
+      State::Door => {
+        let trim = cache.material(materials, "wall/trim", Self::brown_material);
+        self.door_parts(&faces, trim, &mut parts);
+      }
+      _ => {}
+    };
+    parts
+  }
+
+  fn wall_parts(&self, faces: &[Handle<StandardMaterial>; 2], parts: &mut Vec<Part>) {
+    self.piece(
+      faces,
+      Vec3::new(0., WALL_H, self.len),
+      Vec3::new(0., WALL_H_2, 0.),
+      Some(Collider::cuboid(WALL_W, WALL_H_2, self.len / 2.)),
+      parts,
+    );
+  }
+
+  /// A box `size.y` by `size.z` across the whole thickness of the wall, made of one slab per face.
+  fn piece(
+    &self,
+    faces: &[Handle<StandardMaterial>; 2],
+    size: Vec3,
+    translation: Vec3,
+    collider: Option<Collider>,
+    parts: &mut Vec<Part>,
+  ) {
+    for (face, side) in faces.iter().zip([self.inner_side, -self.inner_side]) {
+      parts.push(Part::Mesh {
+        key: MeshKey::cuboid(WALL_W_2, size.y, size.z),
+        material: face.clone(),
+        transform: Transform::from_translation(translation + Vec3::X * side * WALL_W_2 / 2.),
+      });
+    }
+    if let Some(collider) = collider {
+      parts.push(Part::Collider {
+        collider,
+        transform: Transform::from_translation(translation),
+      });
+    }
+  }
+
+  fn door_parts(
+    &self,
+    faces: &[Handle<StandardMaterial>; 2],
+    trim: Handle<StandardMaterial>,
+    parts: &mut Vec<Part>,
+  ) {
     let width = self.len / 2. - DOOR_W_2;
-    let material = materials.add(Self::white_material());
-    let mesh = meshes.add(Mesh::from(shape::Box::new(WALL_W, WALL_H, width)));
     let collider = Collider::cuboid(WALL_W, WALL_H_2, width / 2.);
+    let size = Vec3::new(0., WALL_H, width);
 
-    ec.with_children(|child_builder| {
-      // left side
-      child_builder
-        .spawn_bundle(PbrBundle {
-          mesh: mesh.clone(),
-          material: material.clone(),
-          transform: Transform::from_xyz(0., WALL_H_2, -(width / 2. + DOOR_W_2)),
-          ..default()
-        })
-        .insert(collider.clone());
-
-      // right side
-      let right_wall = child_builder
-        .spawn_bundle(PbrBundle {
-          mesh,
-          transform: Transform::from_xyz(0., WALL_H_2, width / 2. + DOOR_W_2),
-          material: material.clone(),
-          ..default()
-        })
-        .insert(collider)
-        .id();
-
-      // above door
-      child_builder.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Box::new(WALL_W, WALL_H_4, DOOR_W))),
-        transform: Transform::from_xyz(0., CELL_SIZE * (7. / 16.), 0.),
-        material,
-        ..default()
-      });
+    // left side
+    self.piece(
+      faces,
+      size,
+      Vec3::new(0., WALL_H_2, -(width / 2. + DOOR_W_2)),
+      Some(collider.clone()),
+      parts,
+    );
 
-      let material = materials.add(Self::brown_material());
-      let mesh = meshes.add(Mesh::from(shape::Box::new(
-        FRAME_W,
-        WALL_H - WALL_H_4,
-        FRAME_W,
-      )));
-
-      // right trim
-      child_builder.spawn_bundle(PbrBundle {
-        mesh: mesh.clone(),
-        transform: Transform::from_xyz(0., WALL_H_2 - WALL_H_8, DOOR_W_2),
-        material: material.clone(),
-        ..default()
-      });
+    // right side
+    self.piece(
+      faces,
+      size,
+      Vec3::new(0., WALL_H_2, width / 2. + DOOR_W_2),
+      Some(collider),
+      parts,
+    );
 
-      // left trim
-      child_builder.spawn_bundle(PbrBundle {
-        mesh,
-        transform: Transform::from_xyz(0., WALL_H_2 - WALL_H_8, -DOOR_W_2),
-        material: material.clone(),
-        ..default()
-      });
+    // above door
+    self.piece(
+      faces,
+      Vec3::new(0., WALL_H_4, DOOR_W),
+      Vec3::new(0., CELL_SIZE * (7. / 16.), 0.),
+      None,
+      parts,
+    );
 
-      // top trim
-      child_builder.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Box::new(
-          FRAME_W,
-          FRAME_W,
-          DOOR_W + FRAME_W,
-        ))),
-        transform: Transform::from_xyz(0., WALL_H_2 + WALL_H_4, 0.),
-        material: material.clone(),
-        ..default()
-      });
+    let key = MeshKey::cuboid(FRAME_W, WALL_H - WALL_H_4, FRAME_W);
 
-      // door
-      // let axis = Vec3::X;
-      // let joint = RevoluteJointBuilder::new(axis)
-      // .local_anchor1(Vec3::new(0., 0., 1.))
-      // .local_anchor2(Vec3::new(0., 0., 1.));
-      // child_builder
-      // .spawn_bundle(PbrBundle {
-      // mesh: meshes.add(Mesh::from(shape::Box::new(
-      // WALL_W,
-      // WALL_H - WALL_H_4,
-      // DOOR_W,
-      // ))),
-      // material,
-      // transform: Transform::from_xyz(0., (WALL_H - WALL_H_4) / 2., 0.),
-      // ..default()
-      // })
-      // .insert(RigidBody::Dynamic)
-      // .insert(Collider::cuboid(
-      // WALL_W_2,
-      // (WALL_H - WALL_H_4) / 2.,
-      // DOOR_W_2,
-      // ))
-      // .insert(ImpulseJoint::new(right_wall, joint));
-    })
-    .id()
+    // right trim
+    parts.push(Part::Mesh {
+      key,
+      material: trim.clone(),
+      transform: Transform::from_xyz(0., WALL_H_2 - WALL_H_8, DOOR_W_2),
+    });
+
+    // left trim
+    parts.push(Part::Mesh {
+      key,
+      material: trim.clone(),
+      transform: Transform::from_xyz(0., WALL_H_2 - WALL_H_8, -DOOR_W_2),
+    });
+
+    // top trim
+    parts.push(Part::Mesh {
+      key: MeshKey::cuboid(FRAME_W, FRAME_W, DOOR_W + FRAME_W),
+      material: trim,
+      transform: Transform::from_xyz(0., WALL_H_2 + WALL_H_4, 0.),
+    });
+
+    // the door itself would go here, as a dynamic body of its own that isn't baked
   }
 
   fn white_material() -> StandardMaterial {
@@ -214,7 +328,7 @@ impl Wall {
   }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum State {
   #[default]
   None,
@@ -0,0 +1,361 @@
+use super::CELL_GRID;
+use crate::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+// Everything is relative to the building's origin, with the entrance facing -x.
+const HOUSE_FRONT: f32 = -CELL_SIZE_2;
+/// Where the sidewalk ends, for buildings on a lot.
+const LOT_FRONT: f32 = HOUSE_FRONT - 30.;
+const FENCE_FRONT: f32 = LOT_FRONT + 5.;
+
+/// `models/yard.glb` is a fence section 2 wide and 1 tall.
+const FENCE_SCALE: f32 = 4.;
+const FENCE_W: f32 = 2. * FENCE_SCALE;
+const FENCE_H_2: f32 = FENCE_SCALE / 2.;
+const FENCE_T_2: f32 = 0.25;
+const GATE_W: f32 = 8.;
+const DRIVEWAY_W: f32 = 14.;
+const WALKWAY_W: f32 = 4.;
+
+const TREES: [&str; 5] = [
+  "trees/Tree Type0 03.glb#Scene0",
+  "trees/Tree Type2 05.glb#Scene0",
+  "trees/Tree Type3 04.glb#Scene0",
+  "trees/Tree Type4 04.glb#Scene0",
+  "trees/Tree Type7 02.glb#Scene0",
+];
+const TREE_SCALE: f32 = 4.;
+const TREE_TRUNK_R: f32 = 1.;
+const BUSHES: [&str; 5] = [
+  "trees/Tree Type1 01.glb#Scene0",
+  "trees/Tree Type2 01.glb#Scene0",
+  "trees/Tree Type3 01.glb#Scene0",
+  "trees/Tree Type5 01.glb#Scene0",
+  "trees/Tree Type6 01.glb#Scene0",
+];
+const BUSH_SCALE: f32 = 2.5;
+const BUSH_R: f32 = 1.5;
+
+/// Fences, a driveway and plants around a building, all decided by the building's seed.
+#[derive(Component)]
+pub struct Yard;
+
+/// Where a yard is fenced in, relative to the building's origin before it's turned: around the
+/// property, with room for the walls of cells on its edge, and out to the lot in front.
+pub(super) struct Fence {
+  pub(super) min: Vec3,
+  pub(super) max: Vec3,
+}
+
+impl Fence {
+  pub(super) fn around(origin: &Transform) -> Self {
+    let (min, max) = Building::property(origin)
+      .corners()
+      .map(|c| origin.rotation.inverse() * (c - origin.translation))
+      .into_iter()
+      .fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), c| (min.min(c), max.max(c)),
+      );
+    Self {
+      min: Vec3::new(min.x + FENCE_FRONT - HOUSE_FRONT, 0., min.z - CELL_SIZE_2),
+      max: Vec3::new(max.x + CELL_SIZE, 0., max.z + CELL_SIZE_2),
+    }
+  }
+
+  /// In order around the yard, starting at the front.
+  fn corners(&self) -> [Vec3; 4] {
+    [
+      Vec3::new(self.min.x, 0., self.min.z),
+      Vec3::new(self.min.x, 0., self.max.z),
+      Vec3::new(self.max.x, 0., self.max.z),
+      Vec3::new(self.max.x, 0., self.min.z),
+    ]
+  }
+
+  /// The point nearest `pos`, both relative to the origin, at least `margin` inside the fence.
+  pub(super) fn clamp(&self, pos: Vec3, margin: f32) -> Vec3 {
+    Vec3::new(
+      pos.x.clamp(self.min.x + margin, self.max.x - margin),
+      pos.y,
+      pos.z.clamp(self.min.z + margin, self.max.z - margin),
+    )
+  }
+
+  /// Whether the world position `pos` is in the yard of the lot at `origin`, fence included.
+  fn contains(&self, origin: &Transform, pos: Vec3) -> bool {
+    let pos = origin.rotation.inverse() * (pos - origin.translation);
+    pos.x >= self.min.x && pos.x <= self.max.x && pos.z >= self.min.z && pos.z <= self.max.z
+  }
+}
+
+/// Somewhere to leave clear of fences and plants: a gate, driveway or path.
+struct Opening {
+  /// Along the fence it's in.
+  at: f32,
+  w: f32,
+}
+
+impl Yard {
+  /// `lots` are the origins of the buildings around, a side already fenced by one of them (the
+  /// first by position) is left to it.
+  pub fn fabricate(
+    building: &Building,
+    lots: &[Transform],
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
+  ) {
+    let mut rng = StdRng::seed_from_u64(building.seed ^ Salt::Yard as u64);
+    let fence = Fence::around(&building.origin);
+
+    let driveway = Opening {
+      at: *[-1., 1.].choose(&mut rng).unwrap() * rng.gen_range(50.0..90.),
+      w: DRIVEWAY_W,
+    };
+    let walkway = Opening { at: 0., w: GATE_W };
+    let side_gate = Opening {
+      at: rng.gen_range(HOUSE_FRONT + GATE_W..fence.max.x - GATE_W),
+      w: GATE_W,
+    };
+    let gate_side = *[fence.min.z, fence.max.z].choose(&mut rng).unwrap();
+
+    child_builder
+      .spawn_bundle(TransformBundle::default())
+      .insert_bundle(VisibilityBundle::default())
+      .insert(Self)
+      .with_children(|child_builder| {
+        for (i, from, to) in Self::sides(&building.origin, lots) {
+          let openings: &[&Opening] = match i {
+            0 => &[&driveway, &walkway],
+            1 | 3 if from.z == gate_side => &[&side_gate],
+            _ => &[],
+          };
+          Self::fence(from, to, openings, child_builder, ass);
+        }
+
+        let gray = cache.material(materials, "yard/path", || StandardMaterial {
+          base_color: Color::GRAY,
+          perceptual_roughness: 1.,
+          ..default()
+        });
+        for (opening, w) in [(&driveway, DRIVEWAY_W), (&walkway, WALKWAY_W)] {
+          let len = HOUSE_FRONT - LOT_FRONT;
+          child_builder.spawn_bundle(PbrBundle {
+            mesh: cache.mesh(meshes, MeshKey::cuboid(len, 0.1, w)),
+            material: gray.clone(),
+            transform: Transform::from_xyz(LOT_FRONT + len / 2., 0., opening.at),
+            ..default()
+          });
+        }
+
+        let is_clear = |pos: Vec3, r: f32| {
+          let in_house = [(-r, -r), (-r, r), (r, -r), (r, r)]
+            .into_iter()
+            .any(|(x, z)| {
+              building
+                .cells
+                .contains_key(&CELL_GRID.coord(&(pos + Vec3::new(x, 0., z))))
+            });
+          let on_path = pos.x < HOUSE_FRONT
+            && [&driveway, &walkway]
+              .into_iter()
+              .any(|o| (pos.z - o.at).abs() < o.w / 2. + r);
+          let by_fence = pos.x - r < fence.min.x
+            || pos.x + r > fence.max.x
+            || pos.z - r < fence.min.z
+            || pos.z + r > fence.max.z;
+          !in_house && !on_path && !by_fence
+        };
+
+        for (models, count, scale, r, trunk) in [
+          (
+            &TREES,
+            rng.gen_range(1..=4),
+            TREE_SCALE,
+            TREE_TRUNK_R * 4.,
+            true,
+          ),
+          (&BUSHES, rng.gen_range(2..=6), BUSH_SCALE, BUSH_R, false),
+        ] {
+          let mut placed = 0;
+          for _ in 0..count * 10 {
+            if placed == count {
+              break;
+            }
+            let pos = Vec3::new(
+              rng.gen_range(fence.min.x..fence.max.x),
+              0.,
+              rng.gen_range(fence.min.z..fence.max.z),
+            );
+            if !is_clear(pos, r) {
+              continue;
+            }
+
+            let scale = scale * rng.gen_range(0.8..1.2);
+            child_builder.spawn_bundle(SceneBundle {
+              scene: ass.load(*models.choose(&mut rng).unwrap()),
+              transform: Transform::from_translation(pos)
+                .with_rotation(Quat::from_rotation_y(
+                  rng.gen_range(0.0..std::f32::consts::TAU),
+                ))
+                .with_scale(Vec3::splat(scale)),
+              ..default()
+            });
+
+            let (collider, h) = match trunk {
+              true => (Collider::cylinder(scale, TREE_TRUNK_R), scale),
+              false => (Collider::ball(BUSH_R), BUSH_R),
+            };
+            child_builder
+              .spawn()
+              .insert(collider)
+              .insert_bundle(TransformBundle::from(Transform::from_translation(
+                pos + Vec3::Y * h,
+              )));
+            placed += 1;
+          }
+        }
+      });
+  }
+
+  /// The sides of the yard at `origin` it fences itself, by index around it from the front and
+  /// relative to it. Just over a side is where a neighbour's fence along it would be.
+  fn sides(origin: &Transform, lots: &[Transform]) -> Vec<(usize, Vec3, Vec3)> {
+    let fence = Fence::around(origin);
+    let center = (fence.min + fence.max) / 2.;
+    let order = |t: &Transform| (t.translation.x, t.translation.z);
+    let corners = fence.corners();
+
+    (0..corners.len())
+      .map(|i| (i, corners[i], corners[(i + 1) % corners.len()]))
+      .filter(|(_, from, to)| {
+        let mid = (*from + *to) / 2.;
+        let over = origin.mul_vec3(mid + (mid - center).normalize());
+        !lots
+          .iter()
+          .filter(|lot| order(lot) < order(origin))
+          .any(|lot| Fence::around(lot).contains(lot, over))
+      })
+      .collect()
+  }
+
+  /// Fence sections from `from` to `to`, which must be along x or z, leaving gaps for `openings`.
+  fn fence(
+    from: Vec3,
+    to: Vec3,
+    openings: &[&Opening],
+    child_builder: &mut ChildBuilder,
+    ass: &Res<AssetServer>,
+  ) {
+    let along_x = from.z == to.z;
+    let (start, end) = match along_x {
+      true => (from.x, to.x),
+      false => (from.z, to.z),
+    };
+    let (start, end) = (start.min(end), start.max(end));
+    let point = |v: f32| match along_x {
+      true => Vec3::new(v, 0., from.z),
+      false => Vec3::new(from.x, 0., v),
+    };
+    // the model runs along x
+    let rotation = match along_x {
+      true => Quat::IDENTITY,
+      false => Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+    };
+
+    let mut cuts = openings
+      .iter()
+      .map(|o| (o.at - o.w / 2., o.at + o.w / 2.))
+      .collect::<Vec<_>>();
+    cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut runs = vec![];
+    let mut run_start = start;
+    for (cut_start, cut_end) in cuts {
+      runs.push((run_start, cut_start.max(run_start)));
+      run_start = cut_end.min(end);
+    }
+    runs.push((run_start, end));
+
+    let scene = ass.load("models/yard.glb#Scene0");
+    for (a, b) in runs.into_iter().filter(|(a, b)| b - a > 0.1) {
+      let len = b - a;
+      // squeeze the sections a little so a whole number of them fit exactly
+      let n = (len / FENCE_W).ceil();
+      let w = len / n;
+      for i in 0..n as usize {
+        child_builder.spawn_bundle(SceneBundle {
+          scene: scene.clone(),
+          transform: Transform::from_translation(point(a + w * (i as f32 + 0.5)))
+            .with_rotation(rotation)
+            .with_scale(Vec3::new(w / 2., FENCE_SCALE, FENCE_SCALE)),
+          ..default()
+        });
+      }
+
+      child_builder
+        .spawn()
+        .insert(Collider::cuboid(len / 2., FENCE_H_2, FENCE_T_2))
+        .insert_bundle(TransformBundle::from(
+          Transform::from_translation(point((a + b) / 2.) + Vec3::Y * FENCE_H_2)
+            .with_rotation(rotation),
+        ));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Whether `a` and `b` run along the same line and overlap.
+  fn overlap(a: (Vec3, Vec3), b: (Vec3, Vec3)) -> bool {
+    let dir = (a.1 - a.0).normalize();
+    let off_line = |p: Vec3| (p - a.0 - dir * (p - a.0).dot(dir)).length() > 0.1;
+    if off_line(b.0) || off_line(b.1) {
+      return false;
+    }
+    let along = |p: Vec3| (p - a.0).dot(dir);
+    let (b0, b1) = (along(b.0).min(along(b.1)), along(b.0).max(along(b.1)));
+    b0.max(0.) + 0.1 < b1.min(along(a.1))
+  }
+
+  #[test]
+  fn neighbouring_yards_dont_fence_a_side_twice() {
+    for seed in 0..20 {
+      let mut fences = vec![];
+      for z in -1..=1 {
+        for x in -1..=1 {
+          let coord = Coord { x, z };
+          let lots = Chunks::lots_around(seed, coord);
+          for (origin, _) in Chunks::buildings(seed, coord) {
+            for (_, from, to) in Yard::sides(&origin, &lots) {
+              fences.push((
+                origin.translation,
+                origin.mul_vec3(from),
+                origin.mul_vec3(to),
+              ));
+            }
+          }
+        }
+      }
+
+      for (i, a) in fences.iter().enumerate() {
+        for b in &fences[i + 1..] {
+          assert!(
+            a.0 == b.0 || !overlap((a.1, a.2), (b.1, b.2)),
+            "seed {}: yards at {} and {} both fence {} to {}",
+            seed,
+            a.0,
+            b.0,
+            b.1,
+            b.2
+          );
+        }
+      }
+    }
+  }
+}
@@ -1,4 +1,5 @@
-use super::Player;
+use super::{Player, Weapon};
+use crate::{Action, Actions, Noise};
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::time::{Duration, Instant};
@@ -24,25 +25,28 @@ const COLLISION_LENGTH_2: f32 = 15.;
 
 impl Bullet {
   pub fn spawn(
-    input: Res<Input<KeyCode>>,
-    mouse: Res<Input<MouseButton>>,
+    actions: Res<Actions>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     rapier_context: Res<RapierContext>,
-    query: Query<(Entity, &Transform, &Player)>,
+    mut noise: ResMut<Noise>,
+    time: Res<Time>,
+    mut query: Query<(Entity, &Transform, &Player, &mut Weapon)>,
   ) {
     if query.is_empty() {
       return;
     }
 
-    let (player_entity, t, player) = query.single();
+    let (player_entity, t, player, mut weapon) = query.single_mut();
     let theta = player.angle;
 
-    if !input.just_pressed(KeyCode::Space) && !mouse.just_pressed(MouseButton::Left) {
+    if !actions.just_pressed(Action::Fire) || !weapon.fire() {
       return;
     }
 
+    noise.make(t.translation, &time);
+
     let direction = Vec3::new(theta.sin(), 0., theta.cos());
     let transform = Transform::from_translation(t.translation + direction * 2.);
 
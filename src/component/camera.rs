@@ -1,5 +1,5 @@
 use super::Player;
-use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy::{input::mouse::MouseWheel, prelude::*, render::camera::Camera as RenderCamera};
 
 #[derive(Component)]
 pub struct Camera {
@@ -36,4 +36,44 @@ impl Camera {
       .translation
       .lerp(player_transform.translation + Vec3::Y * camera.zoom, 0.2);
   }
+
+  /// Screen "up" and "right" flattened onto the ground plane, so movement keys follow the camera
+  /// no matter how it is tilted or rotated.
+  pub fn ground_axes(camera_transform: &GlobalTransform) -> (Vec3, Vec3) {
+    let flatten = |v: Vec3| Vec3::new(v.x, 0., v.z).normalize_or_zero();
+
+    // looking straight down, forward has no horizontal component but up does (and vice versa)
+    let forward = flatten(flatten(camera_transform.forward()) + flatten(camera_transform.up()));
+    let right = flatten(camera_transform.right());
+    (forward, right)
+  }
+
+  /// Casts a ray from the camera through the cursor and returns where it hits the horizontal
+  /// plane at `height`.
+  pub fn cursor_to_ground(
+    window: &Window,
+    camera: &RenderCamera,
+    camera_transform: &GlobalTransform,
+    height: f32,
+  ) -> Option<Vec3> {
+    let cursor = window.cursor_position()?;
+    let size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / size * 2. - Vec2::ONE;
+
+    // bevy uses an infinite reversed-z projection: the near plane is at z = 1
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    let near = ndc_to_world.project_point3(ndc.extend(1.));
+    let mid = ndc_to_world.project_point3(ndc.extend(0.5));
+    let dir = mid - near;
+
+    if dir.y.abs() < f32::EPSILON {
+      return None;
+    }
+    let t = (height - near.y) / dir.y;
+    if t < 0. {
+      return None;
+    }
+
+    Some(near + dir * t)
+  }
 }
@@ -0,0 +1,270 @@
+use crate::*;
+use bevy::render::{mesh::PrimitiveTopology, view::NoFrustumCulling};
+use bevy_rapier3d::render::DebugRenderContext;
+
+/// Above the roofs, so nothing hides the lines.
+const OVERLAY_HEIGHT: f32 = wall::WALL_H + 2.;
+/// Half the width of the cross marking a point.
+const CROSS: f32 = 1.5;
+
+/// Something to see the game's workings through, drawn over the world when shown.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Overlay {
+  /// Every loaded nav node and where it leads.
+  Nav,
+  /// The path each zombie is following, and where it's headed next.
+  Paths,
+  /// The edges of the `Zones` that have anything in them.
+  Zones,
+  /// The property each loaded building was generated within.
+  Bounds,
+  /// What the physics sees, drawn by rapier.
+  Colliders,
+}
+
+/// Which overlays are shown, toggled with their actions.
+#[derive(Default)]
+pub struct DebugOverlays {
+  shown: HashSet<Overlay>,
+}
+
+/// The lines of a shown overlay, one mesh redrawn every frame.
+#[derive(Component)]
+pub struct OverlayLines(Overlay);
+
+/// Lines to be drawn as one mesh.
+#[derive(Default)]
+struct Lines {
+  positions: Vec<[f32; 3]>,
+  colors: Vec<[f32; 4]>,
+}
+
+impl Overlay {
+  pub const ALL: [Self; 5] = [
+    Self::Nav,
+    Self::Paths,
+    Self::Zones,
+    Self::Bounds,
+    Self::Colliders,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Nav => "nav",
+      Self::Paths => "paths",
+      Self::Zones => "zones",
+      Self::Bounds => "bounds",
+      Self::Colliders => "colliders",
+    }
+  }
+
+  fn action(self) -> Action {
+    match self {
+      Self::Nav => Action::ToggleNavOverlay,
+      Self::Paths => Action::TogglePathOverlay,
+      Self::Zones => Action::ToggleZoneOverlay,
+      Self::Bounds => Action::ToggleBoundsOverlay,
+      Self::Colliders => Action::ToggleColliderOverlay,
+    }
+  }
+}
+
+impl DebugOverlays {
+  pub fn is_shown(&self, overlay: Overlay) -> bool {
+    self.shown.contains(&overlay)
+  }
+
+  pub fn toggle(&mut self, overlay: Overlay) {
+    if !self.shown.remove(&overlay) {
+      self.shown.insert(overlay);
+    }
+  }
+
+  pub fn toggle_pressed(mut this: ResMut<Self>, actions: Res<Actions>) {
+    for overlay in Overlay::ALL {
+      if actions.just_pressed(overlay.action()) {
+        this.toggle(overlay);
+      }
+    }
+  }
+
+  /// Spawns the overlays that were just shown, despawns the ones that were just hidden, and
+  /// redraws the rest.
+  pub fn update(
+    this: Res<Self>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cache: ResMut<AssetCache>,
+    mut rapier_debug: ResMut<DebugRenderContext>,
+    zones: Res<Zones>,
+    zombie_query: Query<(&Transform, &Zombie)>,
+    query: Query<(Entity, &OverlayLines, &Handle<Mesh>)>,
+  ) {
+    rapier_debug.enabled = this.is_shown(Overlay::Colliders);
+
+    let mut spawned = HashSet::new();
+    for (entity, overlay, mesh) in &query {
+      let overlay = overlay.0;
+      if !this.is_shown(overlay) {
+        commands.entity(entity).despawn();
+        continue;
+      }
+
+      spawned.insert(overlay);
+      let lines = match overlay {
+        Overlay::Nav => Self::nav(&zones),
+        Overlay::Paths => Self::paths(&zombie_query),
+        Overlay::Zones => Self::zones(&zones),
+        Overlay::Bounds => Self::bounds(&zones),
+        Overlay::Colliders => continue,
+      };
+      if let Some(mesh) = meshes.get_mut(mesh) {
+        *mesh = lines.into_mesh();
+      }
+    }
+
+    for overlay in Overlay::ALL {
+      if overlay == Overlay::Colliders || !this.is_shown(overlay) || spawned.contains(&overlay) {
+        continue;
+      }
+
+      let material = cache.material(&mut materials, "debug/overlay", || StandardMaterial {
+        base_color: Color::WHITE,
+        unlit: true,
+        ..default()
+      });
+      commands
+        .spawn_bundle(PbrBundle {
+          // drawn for real from the next frame on
+          mesh: meshes.add(Lines::default().into_mesh()),
+          material,
+          ..default()
+        })
+        .insert(NoFrustumCulling)
+        .insert(OverlayLines(overlay));
+    }
+  }
+
+  fn nav(zones: &Zones) -> Lines {
+    let mut lines = Lines::default();
+    for node in zones.zones.values().flat_map(|zone| &zone.nav_nodes) {
+      let color = match node.r#type {
+        NavNodeType::Cell => Color::CYAN,
+        NavNodeType::Door => Color::YELLOW,
+        NavNodeType::Outside => Color::GREEN,
+        NavNodeType::Other => Color::WHITE,
+      };
+      lines.cross(node.pos, color);
+      for adj in &*node.adj.read() {
+        lines.line(node.pos, adj.pos, color);
+      }
+    }
+    lines
+  }
+
+  fn paths(query: &Query<(&Transform, &Zombie)>) -> Lines {
+    let mut lines = Lines::default();
+    for (t, zombie) in query {
+      // the next node is last
+      let mut from = t.translation;
+      for node in zombie.nav().iter().rev() {
+        lines.line(from, node.pos, Color::RED);
+        from = node.pos;
+      }
+      lines.line(t.translation, zombie.dest(), Color::ORANGE);
+      lines.cross(zombie.dest(), Color::ORANGE);
+    }
+    lines
+  }
+
+  fn zones(zones: &Zones) -> Lines {
+    let mut lines = Lines::default();
+    for coord in zones.zones.keys() {
+      lines.rect(&Zones::zone_rect(*coord), Color::FUCHSIA);
+    }
+    lines
+  }
+
+  fn bounds(zones: &Zones) -> Lines {
+    let mut lines = Lines::default();
+    let mut seen = HashSet::new();
+    for building in zones.zones.values().flat_map(|zone| &zone.buildings) {
+      if !seen.insert(Arc::as_ptr(building)) {
+        continue;
+      }
+      if let Some(bounds) = building.bounds() {
+        lines.rect(bounds, Color::BLUE);
+      }
+    }
+    lines
+  }
+}
+
+impl Lines {
+  fn line(&mut self, from: Vec3, to: Vec3, color: Color) {
+    let color = color.as_linear_rgba_f32();
+    for pos in [from, to] {
+      self.positions.push([pos.x, OVERLAY_HEIGHT, pos.z]);
+      self.colors.push(color);
+    }
+  }
+
+  fn cross(&mut self, pos: Vec3, color: Color) {
+    self.line(pos - Vec3::X * CROSS, pos + Vec3::X * CROSS, color);
+    self.line(pos - Vec3::Z * CROSS, pos + Vec3::Z * CROSS, color);
+  }
+
+  fn rect(&mut self, rect: &Rect, color: Color) {
+    let corners = rect.corners();
+    for i in 0..corners.len() {
+      self.line(corners[i], corners[(i + 1) % corners.len()], color);
+    }
+  }
+
+  fn into_mesh(mut self) -> Mesh {
+    // a mesh without vertices can't be drawn
+    if self.positions.is_empty() {
+      self.line(Vec3::ZERO, Vec3::ZERO, Color::NONE);
+    }
+
+    let len = self.positions.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; len]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; len]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+    mesh
+  }
+}
+
+impl ConsoleCommands for DebugOverlays {
+  fn register(console: &mut Console) {
+    console.command(
+      "toggle",
+      "toggle nav|paths|zones|bounds|colliders",
+      "Shows or hides a debug overlay",
+      Self::toggle_command,
+    );
+  }
+}
+
+impl DebugOverlays {
+  fn toggle_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let overlay = args
+      .first()
+      .and_then(|name| Overlay::ALL.into_iter().find(|o| o.name() == *name))
+      .ok_or("Usage: toggle nav|paths|zones|bounds|colliders")?;
+
+    let mut this = world.resource_mut::<Self>();
+    this.toggle(overlay);
+    Ok(format!(
+      "{} overlay {}",
+      overlay.name(),
+      match this.is_shown(overlay) {
+        true => "shown",
+        false => "hidden",
+      }
+    ))
+  }
+}
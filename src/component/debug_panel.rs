@@ -0,0 +1,81 @@
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+
+use crate::*;
+
+/// Numbers for working on the game rather than playing it, toggled with `Action::ToggleDebug`.
+#[derive(Component)]
+pub struct DebugPanel;
+
+impl DebugPanel {
+  pub fn spawn(mut commands: Commands, font: Res<UiFont>) {
+    commands
+      .spawn_bundle(
+        TextBundle::from_section("Debug", font.style(20.)).with_style(Style {
+          align_self: AlignSelf::FlexEnd,
+          position_type: PositionType::Absolute,
+          position: UiRect {
+            top: Val::Px(5.0),
+            left: Val::Px(15.0),
+            ..default()
+          },
+          ..default()
+        }),
+      )
+      .insert(Visibility { is_visible: false })
+      .insert(DebugPanel);
+  }
+
+  pub fn update(
+    actions: Res<Actions>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugPanel>>,
+    zones: Res<Zones>,
+    path_queue: Res<PathQueue>,
+    player_query: Query<&Transform, With<Player>>,
+    diagnostics: Res<Diagnostics>,
+  ) {
+    let (mut text, mut visibility) = match query.get_single_mut() {
+      Ok(panel) => panel,
+      Err(_) => return,
+    };
+    if actions.just_pressed(Action::ToggleDebug) {
+      visibility.is_visible = !visibility.is_visible;
+    }
+    if !visibility.is_visible {
+      return;
+    }
+
+    let fps = diagnostics
+      .get(FrameTimeDiagnosticsPlugin::FPS)
+      .and_then(|fps| fps.average())
+      .unwrap_or_default();
+    let (meshes, materials) = AssetCache::asset_counts(&diagnostics);
+
+    let pos = player_query.iter().next().map(|t| t.translation);
+    let coord = pos
+      .and_then(|pos| zones.cell_at(&pos))
+      .map_or("None".to_string(), |cell| {
+        format!("{},{}", cell.coord.z, cell.coord.x)
+      });
+    let zone_nodes = pos
+      .and_then(|pos| zones.zone(&pos))
+      .map_or(0, |zone| zone.nav_nodes.len());
+    let nav_nodes = zones
+      .zones
+      .values()
+      .map(|zone| zone.nav_nodes.len())
+      .sum::<usize>();
+
+    text.sections[0].value = format!(
+      "FPS: {:.2}\nCoord: {}\nMeshes: {}\nMaterials: {}\nZombies: {}\nNav nodes: {} in this zone, {} in {} zones\nPath requests: {}",
+      fps,
+      coord,
+      meshes,
+      materials,
+      Zombie::count(),
+      zone_nodes,
+      nav_nodes,
+      zones.zones.len(),
+      path_queue.len()
+    );
+  }
+}
@@ -1,45 +1,56 @@
 use crate::*;
 
+const SIZE: f32 = 50.;
+const SIZE_2: f32 = SIZE / 2.;
+
 #[derive(Component)]
 pub struct Grass;
 
 impl Grass {
-  pub fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-  ) {
-    let texture_handle = asset_server.load("grass.jpg");
-    let aspect = 0.25;
-
-    let material = materials.add(StandardMaterial {
-      base_color_texture: Some(texture_handle.clone()),
+  /// Covers a road grid cell in grass tiles, with one collider under all of them.
+  pub fn fabricate(
+    center: Vec3,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    asset_server: &Res<AssetServer>,
+  ) -> Entity {
+    let material = cache.material(materials, "grass", || StandardMaterial {
+      base_color_texture: Some(asset_server.load("grass.jpg")),
       alpha_mode: AlphaMode::Blend,
       reflectance: 0.0,
       perceptual_roughness: 1.,
       // unlit: true,
       ..Default::default()
     });
+    let mesh = cache.mesh(meshes, MeshKey::plane(SIZE));
 
-    const size: f32 = 50.;
-    const size_2: f32 = size / 2.;
-
-    let r = 10;
-    for z in -r..r {
-      for x in -r..r {
-        let transform = Transform::from_xyz(size * x as f32, 0., size * z as f32);
-        commands
-          .spawn_bundle(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane { size })),
-            material: material.clone(),
-            transform,
-            ..default()
-          })
-          .insert(RigidBody::Fixed)
-          .insert(Collider::cuboid(size_2, 0.2, size_2))
-          .insert(Self);
-      }
-    }
+    let r = (road::GRID_SIZE_2 / SIZE) as i32;
+    commands
+      .spawn_bundle(PbrBundle {
+        transform: Transform::from_translation(center),
+        ..default()
+      })
+      .insert(RigidBody::Fixed)
+      .insert(Collider::cuboid(road::GRID_SIZE_2, 0.2, road::GRID_SIZE_2))
+      .insert(Self)
+      .with_children(|child_builder| {
+        for z in -r..r {
+          for x in -r..r {
+            child_builder.spawn_bundle(PbrBundle {
+              mesh: mesh.clone(),
+              material: material.clone(),
+              transform: Transform::from_xyz(
+                SIZE * x as f32 + SIZE_2,
+                0.,
+                SIZE * z as f32 + SIZE_2,
+              ),
+              ..default()
+            });
+          }
+        }
+      })
+      .id()
   }
 }
@@ -26,6 +26,11 @@ impl Health {
     self.changed = true;
   }
 
+  pub fn set_health(&mut self, health: f32) {
+    self.health = health;
+    self.changed = true;
+  }
+
   pub fn is_changed(&self) -> bool {
     self.changed
   }
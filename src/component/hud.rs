@@ -0,0 +1,226 @@
+use crate::{
+  building::{LightSwitch, PowerSwitch, Switch},
+  *,
+};
+
+const BAR_W: f32 = 200.;
+const BAR_H: f32 = 12.;
+const BAR_BACKGROUND: Color = Color::rgba(0., 0., 0., 0.6);
+const FONT_SIZE: f32 = 22.;
+
+/// The font every piece of UI is written in, loaded once.
+pub struct UiFont(pub Handle<Font>);
+
+impl FromWorld for UiFont {
+  fn from_world(world: &mut World) -> Self {
+    Self(world.resource::<AssetServer>().load("FiraMono-Medium.ttf"))
+  }
+}
+
+impl UiFont {
+  pub fn style(&self, font_size: f32) -> TextStyle {
+    TextStyle {
+      font: self.0.clone(),
+      font_size,
+      color: Color::WHITE,
+    }
+  }
+}
+
+/// The player's state at a glance, in the bottom left corner, and what they can interact with
+/// at the bottom of the screen. Only shown while there's a player.
+#[derive(Component)]
+pub struct Hud;
+
+/// What a bar in the HUD fills up with.
+#[derive(Component, Clone, Copy)]
+pub enum HudBar {
+  Health,
+  Stamina,
+  Battery,
+}
+
+/// Weapon, kills and where the player is.
+#[derive(Component)]
+pub struct HudText;
+
+#[derive(Component)]
+pub struct HudPrompt;
+
+impl HudBar {
+  fn color(self) -> Color {
+    match self {
+      Self::Health => Color::rgb(0.8, 0.1, 0.1),
+      Self::Stamina => Color::rgb(0.2, 0.7, 0.3),
+      Self::Battery => Color::rgb(0.9, 0.8, 0.2),
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::Health => "Health",
+      Self::Stamina => "Stamina",
+      Self::Battery => "Battery",
+    }
+  }
+}
+
+impl Hud {
+  pub fn spawn(mut commands: Commands, font: Res<UiFont>) {
+    commands
+      .spawn_bundle(NodeBundle {
+        style: Style {
+          position_type: PositionType::Absolute,
+          position: UiRect {
+            left: Val::Px(15.),
+            bottom: Val::Px(15.),
+            ..default()
+          },
+          // children are laid out bottom up
+          flex_direction: FlexDirection::Column,
+          ..default()
+        },
+        color: Color::NONE.into(),
+        visibility: Visibility { is_visible: false },
+        ..default()
+      })
+      .insert(Self)
+      .with_children(|hud| {
+        hud
+          .spawn_bundle(TextBundle::from_section("", font.style(FONT_SIZE)))
+          .insert(HudText);
+        for bar in [HudBar::Battery, HudBar::Stamina, HudBar::Health] {
+          Self::spawn_bar(hud, &font, bar);
+        }
+      });
+
+    commands
+      .spawn_bundle(
+        TextBundle::from_section("", font.style(FONT_SIZE)).with_style(Style {
+          position_type: PositionType::Absolute,
+          position: UiRect {
+            bottom: Val::Px(80.),
+            left: Val::Percent(40.),
+            ..default()
+          },
+          ..default()
+        }),
+      )
+      .insert(HudPrompt);
+  }
+
+  fn spawn_bar(hud: &mut ChildBuilder, font: &UiFont, bar: HudBar) {
+    hud
+      .spawn_bundle(NodeBundle {
+        style: Style {
+          margin: UiRect {
+            top: Val::Px(4.),
+            ..default()
+          },
+          align_items: AlignItems::Center,
+          ..default()
+        },
+        color: Color::NONE.into(),
+        ..default()
+      })
+      .with_children(|row| {
+        row
+          .spawn_bundle(NodeBundle {
+            style: Style {
+              size: Size::new(Val::Px(BAR_W), Val::Px(BAR_H)),
+              ..default()
+            },
+            color: BAR_BACKGROUND.into(),
+            ..default()
+          })
+          .with_children(|background| {
+            background
+              .spawn_bundle(NodeBundle {
+                style: Style {
+                  size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                  ..default()
+                },
+                color: bar.color().into(),
+                ..default()
+              })
+              .insert(bar);
+          });
+        row.spawn_bundle(
+          TextBundle::from_section(bar.label(), font.style(FONT_SIZE * 0.7)).with_style(Style {
+            margin: UiRect {
+              left: Val::Px(8.),
+              ..default()
+            },
+            ..default()
+          }),
+        );
+      });
+  }
+
+  pub fn update(
+    kills: Res<Kills>,
+    zones: Res<Zones>,
+    controls: Res<Controls>,
+    player_query: Query<(&Transform, &Health, &Stamina, &Weapon), With<Player>>,
+    flashlight_query: Query<&Flashlight>,
+    switch_query: Query<(&GlobalTransform, &LightSwitch)>,
+    source_query: Query<(&GlobalTransform, &PowerSwitch)>,
+    mut hud_query: Query<&mut Visibility, (With<Self>, Without<HudPrompt>)>,
+    mut bar_query: Query<(&HudBar, &mut Style)>,
+    mut text_query: Query<&mut Text, (With<HudText>, Without<HudPrompt>)>,
+    mut prompt_query: Query<(&mut Text, &mut Visibility), With<HudPrompt>>,
+  ) {
+    let player = player_query.iter().next();
+    for mut visibility in &mut hud_query {
+      if visibility.is_visible != player.is_some() {
+        visibility.is_visible = player.is_some();
+      }
+    }
+    let (pt, health, stamina, weapon) = match player {
+      Some(player) => player,
+      None => {
+        for (_, mut visibility) in &mut prompt_query {
+          visibility.is_visible = false;
+        }
+        return;
+      }
+    };
+    let flashlight = flashlight_query.iter().next();
+
+    for (bar, mut style) in &mut bar_query {
+      let fill = match bar {
+        HudBar::Health => health.health(),
+        HudBar::Stamina => stamina.stamina,
+        HudBar::Battery => flashlight.map_or(0., |flashlight| flashlight.battery),
+      };
+      style.size.width = Val::Percent(fill.clamp(0., 1.) * 100.);
+    }
+
+    let ammo = match weapon.is_reloading() {
+      true => "reloading".to_string(),
+      false => format!("{}/{}", weapon.loaded, weapon.magazine),
+    };
+    let room = zones
+      .cell_at(&pt.translation)
+      .map_or("Outside", |cell| cell.room.name());
+    let spares = flashlight.map_or(0, |flashlight| flashlight.spares);
+    for mut text in &mut text_query {
+      text.sections[0].value = format!(
+        "{}\n{} {}\nKills: {}\nSpare batteries: {}",
+        room, weapon.name, ammo, kills.0, spares
+      );
+    }
+
+    let switch = Switch::nearest(pt.translation, switch_query.iter(), source_query.iter());
+    for (mut text, mut visibility) in &mut prompt_query {
+      visibility.is_visible = switch.is_some();
+      if let Some(switch) = &switch {
+        text.sections[0].value = format!(
+          "{}: {}",
+          controls.describe(Action::Interact),
+          switch.describe()
+        );
+      }
+    }
+  }
+}
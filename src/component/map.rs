@@ -0,0 +1,497 @@
+use crate::{
+  building::cell::WALL,
+  road::{RoadGrid, ROAD_WIDTH},
+  *,
+};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+/// World units per pixel.
+const MINIMAP_SCALE: f32 = 2.;
+const MINIMAP_SIZE: u32 = 200;
+const OVERVIEW_SCALE: f32 = 8.;
+const OVERVIEW_SIZE: u32 = 600;
+/// Seconds between redrawing the map, it doesn't need to keep up with every frame.
+const REFRESH: f32 = 0.25;
+
+/// The ground is remembered in squares this big, once the player has been this close to them.
+const EXPLORE_GRID: Grid = Grid::centered(50.);
+const EXPLORE_RADIUS: f32 = 150.;
+
+const WALL_W: f32 = 1.;
+const PLAYER_R: f32 = 3.;
+
+const UNEXPLORED: [u8; 4] = [8, 8, 10, 220];
+const GROUND: [u8; 4] = [32, 48, 32, 230];
+const ROAD: [u8; 4] = [85, 85, 90, 255];
+/// Buildings from the outside, and rooms that haven't been seen.
+const BUILDING: [u8; 4] = [60, 54, 54, 255];
+const FLOOR: [u8; 4] = [140, 125, 100, 255];
+const WALL_COLOR: [u8; 4] = [235, 235, 230, 255];
+const WINDOW: [u8; 4] = [120, 170, 220, 255];
+const PLAYER: [u8; 4] = [255, 60, 60, 255];
+
+/// Where the player has been. Buildings remember which of their rooms have been seen themselves.
+#[derive(Default)]
+pub struct MapMemory {
+  pub explored: HashSet<Coord>,
+  /// Buildings of chunks that have been unloaded, laid out again for the map. Kept until their
+  /// chunk loads again, since what's seen in them can only change then.
+  layouts: HashMap<Coord, Vec<Arc<Building>>>,
+}
+
+impl MapMemory {
+  /// Remembers everything within `EXPLORE_RADIUS` of `pos`.
+  pub fn explore(&mut self, pos: Vec3) {
+    let min = EXPLORE_GRID.coord(&(pos - Vec3::new(EXPLORE_RADIUS, 0., EXPLORE_RADIUS)));
+    let max = EXPLORE_GRID.coord(&(pos + Vec3::new(EXPLORE_RADIUS, 0., EXPLORE_RADIUS)));
+    for x in min.x..=max.x {
+      for z in min.z..=max.z {
+        let coord = Coord { x, z };
+        if EXPLORE_GRID.center(coord).distance(pos) <= EXPLORE_RADIUS {
+          self.explored.insert(coord);
+        }
+      }
+    }
+  }
+
+  pub fn is_explored(&self, pos: &Vec3) -> bool {
+    self.explored.contains(&EXPLORE_GRID.coord(pos))
+  }
+
+  /// The buildings of the chunks in `coords` that have been loaded before but aren't now.
+  pub fn remembered_buildings(
+    &mut self,
+    seed: u64,
+    chunks: &Chunks,
+    coords: impl Iterator<Item = Coord>,
+  ) -> Vec<Arc<Building>> {
+    self.layouts.retain(|coord, _| !chunks.is_loaded(*coord));
+
+    let mut buildings = vec![];
+    for coord in coords.filter(|coord| !chunks.is_loaded(*coord)) {
+      // a chunk that was never generated has nothing to remember until it's been loaded, which
+      // drops it from `layouts` again
+      let layout = self
+        .layouts
+        .entry(coord)
+        .or_insert_with(|| chunks.remembered_buildings(seed, coord).unwrap_or_default());
+      buildings.extend(layout.iter().cloned());
+    }
+    buildings
+  }
+
+  /// A fresh world hasn't been explored yet.
+  pub fn reset(mut this: ResMut<Self>) {
+    *this = Self::default();
+  }
+}
+
+/// A top-down picture of the world around `center`, drawn on the CPU from the generated layout,
+/// so it comes out the same with or without a window. Up is +x and right is +z, like the camera.
+pub struct MapCanvas {
+  pub width: u32,
+  pub height: u32,
+  center: Vec3,
+  /// World units per pixel.
+  scale: f32,
+  /// RGBA, row by row from the top.
+  pub data: Vec<u8>,
+}
+
+impl MapCanvas {
+  pub fn new(width: u32, height: u32, center: Vec3, scale: f32) -> Self {
+    Self {
+      width,
+      height,
+      center,
+      scale,
+      data: UNEXPLORED.repeat((width * height) as usize),
+    }
+  }
+
+  /// Draws everything known about the world: explored ground and roads, buildings with the rooms
+  /// that have been seen, and the player facing `angle`.
+  pub fn render<'a>(
+    mut self,
+    seed: u64,
+    buildings: impl IntoIterator<Item = &'a Arc<Building>>,
+    memory: &MapMemory,
+    player: Option<(Vec3, f32)>,
+  ) -> Self {
+    self.data = GROUND.repeat((self.width * self.height) as usize);
+    self.draw_roads(seed);
+    for building in buildings {
+      self.draw_building(building);
+    }
+    self.hide_unexplored(memory);
+    if let Some((pos, angle)) = player {
+      self.draw_player(pos, angle);
+    }
+    self
+  }
+
+  pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+    let i = ((y * self.width + x) * 4) as usize;
+    [
+      self.data[i],
+      self.data[i + 1],
+      self.data[i + 2],
+      self.data[i + 3],
+    ]
+  }
+
+  fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+    let i = ((y * self.width + x) * 4) as usize;
+    self.data[i..i + 4].copy_from_slice(&color);
+  }
+
+  /// Where the middle of a pixel is in the world.
+  fn to_world(&self, x: u32, y: u32) -> Vec3 {
+    Vec3::new(
+      self.center.x - (y as f32 + 0.5 - self.height as f32 / 2.) * self.scale,
+      0.,
+      self.center.z + (x as f32 + 0.5 - self.width as f32 / 2.) * self.scale,
+    )
+  }
+
+  /// Which pixel `pos` is in, even if it's off the canvas.
+  fn to_pixel(&self, pos: Vec3) -> Vec2 {
+    Vec2::new(
+      (pos.z - self.center.z) / self.scale + self.width as f32 / 2.,
+      (self.center.x - pos.x) / self.scale + self.height as f32 / 2.,
+    )
+  }
+
+  /// Fills a rectangle on the ground, `half` along `axis` and across it. Never thinner than a
+  /// pixel, so walls still show when zoomed out.
+  fn fill_rect(&mut self, center: Vec3, axis: Vec3, half: Vec2, color: [u8; 4]) {
+    let axis = Vec3::new(axis.x, 0., axis.z).normalize_or_zero();
+    let across = Vec3::Y.cross(axis);
+    let half = half.max(Vec2::splat(self.scale / 2.));
+
+    let corners = [(-1., -1.), (-1., 1.), (1., -1.), (1., 1.)]
+      .map(|(a, b)| self.to_pixel(center + axis * half.x * a + across * half.y * b));
+    let min = corners.iter().fold(Vec2::splat(f32::MAX), |m, c| m.min(*c));
+    let max = corners.iter().fold(Vec2::splat(f32::MIN), |m, c| m.max(*c));
+    if max.x < 0. || max.y < 0. || min.x >= self.width as f32 || min.y >= self.height as f32 {
+      return;
+    }
+
+    let x_range = min.x.max(0.) as u32..(max.x.ceil() as u32).min(self.width);
+    for y in min.y.max(0.) as u32..(max.y.ceil() as u32).min(self.height) {
+      for x in x_range.clone() {
+        let d = self.to_world(x, y) - Vec3::new(center.x, 0., center.z);
+        if d.dot(axis).abs() <= half.x && d.dot(across).abs() <= half.y {
+          self.set_pixel(x, y, color);
+        }
+      }
+    }
+  }
+
+  fn line(&mut self, from: Vec3, to: Vec3, width: f32, color: [u8; 4]) {
+    let along = to - from;
+    let half = Vec2::new(Vec2::new(along.x, along.z).length() / 2., width / 2.);
+    self.fill_rect((from + to) / 2., along, half, color);
+  }
+
+  fn circle(&mut self, center: Vec3, radius: f32, color: [u8; 4]) {
+    let c = self.to_pixel(center);
+    let r = radius / self.scale;
+    for y in (c.y - r).max(0.) as u32..((c.y + r).ceil().max(0.) as u32).min(self.height) {
+      for x in (c.x - r).max(0.) as u32..((c.x + r).ceil().max(0.) as u32).min(self.width) {
+        if Vec2::new(x as f32 + 0.5, y as f32 + 0.5).distance(c) <= r {
+          self.set_pixel(x, y, color);
+        }
+      }
+    }
+  }
+
+  /// The road grid cells with anything on the canvas. Roads and lots leave the min corner, so the
+  /// cells below the canvas can reach onto it too.
+  pub fn chunks(&self) -> impl Iterator<Item = Coord> {
+    let reach = Vec3::new(self.height as f32, 0., self.width as f32) * self.scale / 2.;
+    let min = RoadGrid::coord(&(self.center - reach));
+    let max = RoadGrid::coord(&(self.center + reach));
+    (min.x - 1..=max.x).flat_map(move |x| (min.z - 1..=max.z).map(move |z| Coord { x, z }))
+  }
+
+  /// Roads of every road grid cell on the canvas, generated or not, since they only depend on the
+  /// seed.
+  fn draw_roads(&mut self, seed: u64) {
+    for coord in self.chunks().collect::<Vec<_>>() {
+      for (from, to) in RoadGrid::roads(seed, coord) {
+        self.line(from, to, ROAD_WIDTH, ROAD);
+        self.circle(from, ROAD_WIDTH / 2., ROAD);
+        self.circle(to, ROAD_WIDTH / 2., ROAD);
+      }
+    }
+  }
+
+  /// The building's footprint, with the floors and walls of the rooms that have been seen.
+  fn draw_building(&mut self, building: &Building) {
+    let rotation = building.origin.rotation;
+    let axis = rotation * Vec3::X;
+
+    for cell in building.cells.values() {
+      let color = match cell.room.seen.load(Ordering::Relaxed) {
+        true => FLOOR,
+        false => BUILDING,
+      };
+      self.fill_rect(cell.pos, axis, Vec2::splat(CELL_SIZE_2), color);
+    }
+
+    for cell in building.cells.values() {
+      if !cell.room.seen.load(Ordering::Relaxed) {
+        continue;
+      }
+      for (i, [from, to]) in WALL.iter().enumerate() {
+        let (from, to) = (cell.pos + rotation * *from, cell.pos + rotation * *to);
+        match cell.wall_at(building, i) {
+          wall::State::None => {}
+          wall::State::Solid => self.line(from, to, WALL_W, WALL_COLOR),
+          wall::State::Window => self.line(from, to, WALL_W, WINDOW),
+          wall::State::Door => {
+            let middle = (from + to) / 2.;
+            let gap = (to - from).normalize() * wall::DOOR_W_2;
+            self.line(from, middle - gap, WALL_W, WALL_COLOR);
+            self.line(middle + gap, to, WALL_W, WALL_COLOR);
+          }
+        }
+      }
+    }
+  }
+
+  fn hide_unexplored(&mut self, memory: &MapMemory) {
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if !memory.is_explored(&self.to_world(x, y)) {
+          self.set_pixel(x, y, UNEXPLORED);
+        }
+      }
+    }
+  }
+
+  /// A dot with a line the way the player is facing, the same size however far the map is zoomed.
+  fn draw_player(&mut self, pos: Vec3, angle: f32) {
+    let facing = Vec3::new(angle.sin(), 0., angle.cos());
+    let r = PLAYER_R * self.scale;
+    self.line(pos, pos + facing * r * 2.5, self.scale, PLAYER);
+    self.circle(pos, r, PLAYER);
+  }
+
+  pub fn into_image(self) -> Image {
+    Image::new(
+      Extent3d {
+        width: self.width,
+        height: self.height,
+        depth_or_array_layers: 1,
+      },
+      TextureDimension::D2,
+      self.data,
+      TextureFormat::Rgba8UnormSrgb,
+    )
+  }
+}
+
+/// The map in the corner of the screen, or the overview over the middle of it, swapped with
+/// `Action::ToggleMap`.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum Map {
+  Minimap,
+  Overview,
+}
+
+impl Map {
+  fn size(self) -> u32 {
+    match self {
+      Self::Minimap => MINIMAP_SIZE,
+      Self::Overview => OVERVIEW_SIZE,
+    }
+  }
+
+  fn scale(self) -> f32 {
+    match self {
+      Self::Minimap => MINIMAP_SCALE,
+      Self::Overview => OVERVIEW_SCALE,
+    }
+  }
+
+  /// Both maps, hidden until there's a world to show.
+  pub fn spawn(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    for map in [Self::Minimap, Self::Overview] {
+      let size = map.size();
+      let image = MapCanvas::new(size, size, Vec3::ZERO, map.scale()).into_image();
+      let position = match map {
+        Self::Minimap => UiRect {
+          top: Val::Px(10.),
+          right: Val::Px(10.),
+          ..default()
+        },
+        Self::Overview => UiRect {
+          top: Val::Px(60.),
+          left: Val::Percent(50.),
+          ..default()
+        },
+      };
+      let margin = match map {
+        Self::Minimap => UiRect::default(),
+        // centered horizontally
+        Self::Overview => UiRect {
+          left: Val::Px(-(size as f32) / 2.),
+          ..default()
+        },
+      };
+
+      commands
+        .spawn_bundle(ImageBundle {
+          style: Style {
+            size: Size::new(Val::Px(size as f32), Val::Px(size as f32)),
+            position_type: PositionType::Absolute,
+            position,
+            margin,
+            ..default()
+          },
+          image: UiImage(images.add(image)),
+          visibility: Visibility { is_visible: false },
+          ..default()
+        })
+        .insert(map);
+    }
+  }
+
+  /// Remembers where the player has been, and redraws whichever map is showing every `REFRESH`
+  /// seconds.
+  pub fn update(
+    time: Res<Time>,
+    mut since_refresh: Local<f32>,
+    mut showing: Local<Option<Self>>,
+    actions: Res<Actions>,
+    seed: Res<WorldSeed>,
+    chunks: Res<Chunks>,
+    mut memory: ResMut<MapMemory>,
+    mut images: ResMut<Assets<Image>>,
+    player_query: Query<(&Transform, &Player)>,
+    mut query: Query<(&Self, &UiImage, &mut Visibility)>,
+  ) {
+    let (pt, player) = match player_query.iter().next() {
+      Some(player) => player,
+      None => return,
+    };
+    memory.explore(pt.translation);
+
+    let toggled = actions.just_pressed(Action::ToggleMap);
+    let shown = match (*showing, toggled) {
+      (Some(Self::Minimap), true) => Self::Overview,
+      (Some(Self::Overview), true) => Self::Minimap,
+      (Some(shown), false) => shown,
+      (None, _) => Self::Minimap,
+    };
+
+    *since_refresh += time.delta_seconds();
+    if *showing == Some(shown) && *since_refresh < REFRESH {
+      return;
+    }
+    *since_refresh = 0.;
+    *showing = Some(shown);
+
+    for (map, image, mut visibility) in &mut query {
+      visibility.is_visible = *map == shown;
+      if *map != shown {
+        continue;
+      }
+
+      let size = map.size();
+      let canvas = MapCanvas::new(size, size, pt.translation, map.scale());
+      // explored buildings stay on the map after their chunk is unloaded
+      let remembered = memory.remembered_buildings(seed.0, &chunks, canvas.chunks());
+      let canvas = canvas.render(
+        seed.0,
+        chunks.loaded_buildings().chain(&remembered),
+        &memory,
+        Some((pt.translation, player.angle)),
+      );
+      if let Some(image) = images.get_mut(&image.0) {
+        image.data = canvas.data;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SEED: u64 = 7;
+  const SIZE: u32 = 600;
+
+  fn pixel_at(canvas: &MapCanvas, pos: Vec3) -> [u8; 4] {
+    let p = canvas.to_pixel(pos);
+    canvas.pixel(p.x as u32, p.y as u32)
+  }
+
+  /// Draws the home chunk the way the map remembers it once unloaded, with only its first room
+  /// seen, and checks what ends up where.
+  #[test]
+  fn renders_remembered_chunk() {
+    let coord = RoadGrid::coord(&Vec3::ZERO);
+    let mut chunks = Chunks::default();
+    chunks.saved.insert(
+      coord,
+      ChunkState {
+        seen_rooms: vec![vec![0]],
+        ..default()
+      },
+    );
+    let buildings = chunks.remembered_buildings(SEED, coord).unwrap();
+    let home = &buildings[0];
+    let seen = home.rooms_in_order().next().unwrap().id;
+
+    let mut memory = MapMemory::default();
+    for cell in home.cells.values() {
+      memory.explore(cell.pos);
+    }
+    let canvas = MapCanvas::new(SIZE, SIZE, Vec3::ZERO, MINIMAP_SCALE);
+    // a stretch of road on the canvas, away from any building
+    let road = canvas
+      .chunks()
+      .flat_map(|coord| RoadGrid::roads(SEED, coord))
+      .map(|(from, to)| (from + to) / 2.)
+      .find(|pos| {
+        canvas.to_world(0, 0).x > pos.x
+          && pos.x > canvas.to_world(0, SIZE - 1).x
+          && canvas.to_world(0, 0).z < pos.z
+          && pos.z < canvas.to_world(SIZE - 1, 0).z
+          && buildings.iter().all(|b| !b.bounds().unwrap().contains(pos))
+      })
+      .unwrap();
+    memory.explore(road);
+    assert!(!memory.is_explored(&canvas.to_world(0, 0)));
+
+    let canvas = canvas.render(SEED, &buildings, &memory, Some((Vec3::ZERO, 0.)));
+
+    assert_eq!(canvas.pixel(SIZE / 2, SIZE / 2), PLAYER);
+    assert_eq!(canvas.pixel(0, 0), UNEXPLORED);
+    assert_eq!(pixel_at(&canvas, road), ROAD);
+
+    // away from the player drawn over them
+    let far = |pos: &Vec3| pos.distance(Vec3::ZERO) > CELL_SIZE;
+    let cells = || home.cells.values().filter(|cell| far(&cell.pos));
+    let seen_cell = cells().find(|cell| cell.room.id == seen).unwrap();
+    assert_eq!(pixel_at(&canvas, seen_cell.pos), FLOOR);
+    let unseen_cell = cells().find(|cell| cell.room.id != seen).unwrap();
+    assert_eq!(pixel_at(&canvas, unseen_cell.pos), BUILDING);
+
+    let wall = home
+      .cells
+      .values()
+      .filter(|cell| cell.room.id == seen)
+      .flat_map(|cell| {
+        (0..4)
+          .filter(|i| cell.wall_at(home, *i) == wall::State::Solid)
+          .map(|i| cell.pos + home.origin.rotation * building::cell::WALL_NAV[i])
+      })
+      .find(far)
+      .unwrap();
+    assert_eq!(pixel_at(&canvas, wall), WALL_COLOR);
+  }
+}
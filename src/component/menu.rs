@@ -0,0 +1,104 @@
+use crate::*;
+use bevy::app::AppExit;
+
+/// Full screen overlay shown for every state that isn't `Playing`.
+#[derive(Component)]
+pub struct Menu;
+
+impl Menu {
+  fn spawn(commands: &mut Commands, font: &UiFont, text: String, alpha: f32) {
+    commands
+      .spawn_bundle(NodeBundle {
+        style: Style {
+          size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+          position_type: PositionType::Absolute,
+          justify_content: JustifyContent::Center,
+          align_items: AlignItems::Center,
+          ..default()
+        },
+        color: Color::rgba(0., 0., 0., alpha).into(),
+        ..default()
+      })
+      .with_children(|menu| {
+        menu.spawn_bundle(TextBundle::from_section(text, font.style(40.)));
+      })
+      .insert(Menu);
+  }
+
+  pub fn despawn(mut commands: Commands, query: Query<Entity, With<Menu>>) {
+    for e in &query {
+      commands.entity(e).despawn_recursive();
+    }
+  }
+
+  pub fn spawn_main(mut commands: Commands, controls: Res<Controls>, font: Res<UiFont>) {
+    let text = format!(
+      "Dungeon\n\n{}: Start\n{}: Quit",
+      controls.describe(Action::Interact),
+      controls.describe(Action::Quit)
+    );
+    Self::spawn(&mut commands, &font, text, 1.);
+  }
+
+  pub fn spawn_pause(mut commands: Commands, controls: Res<Controls>, font: Res<UiFont>) {
+    let text = format!(
+      "Paused\n\n{}: Resume\n{}: Restart\n{}: New world\n{}: Quit",
+      controls.describe(Action::Pause),
+      controls.describe(Action::Interact),
+      controls.describe(Action::Reload),
+      controls.describe(Action::Quit)
+    );
+    Self::spawn(&mut commands, &font, text, 0.6);
+  }
+
+  pub fn spawn_game_over(mut commands: Commands, controls: Res<Controls>, font: Res<UiFont>) {
+    let text = format!(
+      "You died\n\n{}: Restart\n{}: New world\n{}: Quit",
+      controls.describe(Action::Interact),
+      controls.describe(Action::Reload),
+      controls.describe(Action::Quit)
+    );
+    Self::spawn(&mut commands, &font, text, 0.6);
+  }
+
+  /// Handles input for the main menu and game over screens.
+  pub fn start_or_quit(
+    mut actions: ResMut<Actions>,
+    mut state: ResMut<State<GameState>>,
+    mut seed: ResMut<WorldSeed>,
+    mut exit: EventWriter<AppExit>,
+  ) {
+    if actions.consume(Action::Interact) {
+      let _ = state.set(GameState::Loading);
+    } else if actions.consume(Action::Reload) {
+      *seed = WorldSeed::default();
+      let _ = state.set(GameState::Loading);
+    } else if actions.consume(Action::Quit) {
+      exit.send(AppExit);
+    }
+  }
+
+  pub fn pause(mut actions: ResMut<Actions>, mut state: ResMut<State<GameState>>) {
+    if actions.consume(Action::Pause) {
+      let _ = state.push(GameState::Paused);
+    }
+  }
+
+  pub fn paused(
+    mut actions: ResMut<Actions>,
+    mut state: ResMut<State<GameState>>,
+    mut seed: ResMut<WorldSeed>,
+    mut exit: EventWriter<AppExit>,
+  ) {
+    if actions.consume(Action::Pause) {
+      let _ = state.pop();
+    } else if actions.consume(Action::Interact) {
+      let _ = state.replace(GameState::Loading);
+    } else if actions.consume(Action::Reload) {
+      *seed = WorldSeed::default();
+      let _ = state.replace(GameState::Loading);
+    } else if actions.consume(Action::Quit) {
+      exit.send(AppExit);
+    }
+  }
+}
@@ -1,7 +1,11 @@
 pub mod camera;
 pub use camera::Camera;
 pub mod player;
-pub use player::Player;
+pub use player::{
+  flashlight::{Flashlight, Lit},
+  weapon::Weapon,
+  God, Player, Stamina,
+};
 pub mod grass;
 pub use grass::Grass;
 pub mod building;
@@ -21,11 +25,15 @@ pub mod zombie;
 pub use zombie::*;
 pub mod navigatable;
 pub use navigatable::Navigatable;
-pub mod debug_line;
-pub use debug_line::DebugLine;
-pub mod debug_square;
-pub use debug_square::DebugSquare;
-pub mod debug_text;
-pub use debug_text::DebugText;
+pub mod debug_overlay;
+pub use debug_overlay::{DebugOverlays, Overlay};
+pub mod debug_panel;
+pub use debug_panel::DebugPanel;
+pub mod hud;
+pub use hud::{Hud, UiFont};
 pub mod health;
 pub use health::*;
+pub mod map;
+pub use map::{Map, MapCanvas, MapMemory};
+pub mod menu;
+pub use menu::Menu;
@@ -1,10 +1,32 @@
+use super::Camera;
 use crate::*;
+use bevy::render::camera::Camera as RenderCamera;
 
-mod flashlight;
+pub mod flashlight;
+pub mod weapon;
+
+/// How far ahead of the player a gamepad stick aims.
+const AIM_DISTANCE: f32 = 50.;
+/// Fraction of a full stamina used per second of sprinting, and recovered per second of not.
+const SPRINT_DRAIN: f32 = 1. / 6.;
+const STAMINA_RECOVERY: f32 = 1. / 10.;
 
 #[derive(Component)]
 pub struct Player {
   pub angle: f32,
+  /// World-space point on the ground the cursor is over.
+  pub aim: Vec3,
+}
+
+/// Keeps the player's health and stamina full, toggled with `god`.
+#[derive(Component)]
+pub struct God;
+
+/// How much longer the player can sprint for.
+#[derive(Component)]
+pub struct Stamina {
+  /// 0 to 1.
+  pub stamina: f32,
 }
 
 impl Player {
@@ -34,7 +56,7 @@ impl Player {
         });
       })
       .with_children(|player| {
-        flashlight::Flashlight::setup(player, meshes, materials);
+        flashlight::Flashlight::setup(player);
       })
       .insert(RigidBody::Dynamic)
       .insert(ExternalForce::default())
@@ -51,38 +73,64 @@ impl Player {
         coefficient: 0.,
         combine_rule: CoefficientCombineRule::Min,
       })
-      .insert(Self { angle: 0. });
+      .insert(weapon::Weapon::pistol())
+      .insert(Stamina { stamina: 1. })
+      .insert(Self {
+        angle: 0.,
+        aim: Vec3::ZERO,
+      });
   }
 
   pub fn update(
-    input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    actions: Res<Actions>,
     window: Res<Windows>,
-    mut query: Query<(&Velocity, &mut ExternalForce, &mut Transform, &mut Player)>,
+    camera_query: Query<(&RenderCamera, &GlobalTransform), With<Camera>>,
+    mut query: Query<(
+      &Velocity,
+      &mut ExternalForce,
+      &mut Transform,
+      &mut Player,
+      &mut Stamina,
+    )>,
   ) {
-    if input.pressed(KeyCode::Q) {
-      std::process::exit(1);
-    }
-    if query.is_empty() {
+    if query.is_empty() || camera_query.is_empty() {
       return;
     }
 
-    let (_vel, mut force, mut pos, mut player) = query.single_mut();
-    let up = input.any_pressed([KeyCode::W, KeyCode::Up]);
-    let down = input.any_pressed([KeyCode::S, KeyCode::Down]);
-    let left = input.any_pressed([KeyCode::A, KeyCode::Left]);
-    let right = input.any_pressed([KeyCode::D, KeyCode::Right]);
-    let x = (-(down as i8) + up as i8) as f32;
-    let z = (-(left as i8) + right as i8) as f32;
+    let (_vel, mut force, mut pos, mut player, mut stamina) = query.single_mut();
+    let (camera, camera_transform) = camera_query.single();
 
-    let scale = 25000.;
-    force.force = Vec3::new(x * scale, 0., z * scale);
+    let (forward_dir, right_dir) = Camera::ground_axes(camera_transform);
+    let dir = forward_dir * actions.movement.y + right_dir * actions.movement.x;
 
-    let window = window.get_primary().unwrap();
-    if let Some(cur_pos) = window.cursor_position() {
-      let cx = window.width() / 2.;
-      let cy = window.height() / 2.;
+    let sprinting = actions.pressed(Action::Sprint) && dir != Vec3::ZERO && stamina.stamina > 0.;
+    let dt = time.delta_seconds();
+    stamina.stamina = match sprinting {
+      true => (stamina.stamina - SPRINT_DRAIN * dt).max(0.),
+      false => (stamina.stamina + STAMINA_RECOVERY * dt).min(1.),
+    };
 
-      let angle = (cur_pos.y - cy).atan2(cur_pos.x - cx);
+    let scale = match sprinting {
+      true => 40000.,
+      false => 25000.,
+    };
+    force.force = dir * scale;
+
+    let aim = match actions.aim {
+      Aim::Cursor => {
+        let window = window.get_primary().unwrap();
+        Camera::cursor_to_ground(window, camera, camera_transform, pos.translation.y)
+      }
+      Aim::Stick(stick) => {
+        Some(pos.translation + (forward_dir * stick.y + right_dir * stick.x) * AIM_DISTANCE)
+      }
+    };
+
+    if let Some(aim) = aim {
+      let d = aim - pos.translation;
+      let angle = d.x.atan2(d.z);
+      player.aim = aim;
       player.angle = angle;
       pos.rotation = Quat::from_axis_angle(Vec3::Y, angle);
     }
@@ -125,3 +173,80 @@ impl Player {
     }
   }
 }
+
+impl ConsoleCommands for Player {
+  fn register(console: &mut Console) {
+    console.command(
+      "teleport",
+      "teleport x z",
+      "Moves the player",
+      Self::teleport,
+    );
+    console.command("god", "god", "Toggles invulnerability", Self::god);
+    console.command(
+      "give",
+      "give ammo|battery",
+      "Refills the gun, or hands over a spare battery",
+      Self::give,
+    );
+  }
+}
+
+impl Player {
+  fn teleport(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let usage = "teleport x z";
+    let (x, z): (f32, f32) = (console::arg(args, 0, usage)?, console::arg(args, 1, usage)?);
+
+    let mut query = world.query_filtered::<(&mut Transform, &mut Velocity), With<Self>>();
+    let (mut t, mut velocity) = query.iter_mut(world).next().ok_or("No player")?;
+    t.translation = Vec3::new(x, t.translation.y, z);
+    *velocity = Velocity::zero();
+    Ok(format!("Teleported to {} {}", x, z))
+  }
+
+  fn god(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let mut query = world.query_filtered::<(Entity, Option<&God>), With<Self>>();
+    let (entity, god) = query.iter(world).next().ok_or("No player")?;
+    match god {
+      Some(_) => {
+        world.entity_mut(entity).remove::<God>();
+        Ok("God mode off".into())
+      }
+      None => {
+        world.entity_mut(entity).insert(God);
+        Ok("God mode on".into())
+      }
+    }
+  }
+
+  fn give(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args.first() {
+      Some(&"ammo") => {
+        let mut query = world.query_filtered::<&mut Weapon, With<Self>>();
+        query.iter_mut(world).next().ok_or("No player")?.refill();
+        Ok("Gun refilled".into())
+      }
+      Some(&"battery") => {
+        let mut query = world.query::<&mut Flashlight>();
+        query
+          .iter_mut(world)
+          .next()
+          .ok_or("No flashlight")?
+          .add_battery();
+        Ok("Spare battery given".into())
+      }
+      _ => Err("Usage: give ammo|battery".into()),
+    }
+  }
+}
+
+impl God {
+  pub fn update(mut query: Query<(&mut Health, &mut Stamina), With<Self>>) {
+    for (mut health, mut stamina) in &mut query {
+      if health.health() < 1. {
+        health.set_health(1.);
+      }
+      stamina.stamina = 1.;
+    }
+  }
+}
@@ -1,81 +1,167 @@
-use bevy::prelude::*;
+use crate::*;
+use rand::thread_rng;
+
+const RANGE: f32 = 100.;
+const INTENSITY: f32 = 20000.;
+const OUTER_ANGLE: f32 = 0.45;
+const INNER_ANGLE: f32 = 0.3;
+
+/// Fraction of a full battery used per second while the light is on.
+const DRAIN: f32 = 1. / 180.;
+/// Fraction of a full battery recovered per second while the light is off.
+const RECHARGE: f32 = 1. / 600.;
+/// Below this charge the light starts to flicker.
+const LOW_CHARGE: f32 = 0.15;
+/// Seconds a zombie stands dazzled when a beam first catches it.
+const STUN: f64 = 1.;
 
 #[derive(Component)]
-pub struct Flashlight;
+pub struct Flashlight {
+  pub on: bool,
+  /// Charge of the loaded battery, 0 to 1.
+  pub battery: f32,
+  /// Spare batteries the player is carrying.
+  pub spares: u32,
+}
 
 impl Flashlight {
-  pub fn setup(
-    commands: &mut ChildBuilder,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-  ) {
-    const S: f32 = 0.75;
-    const S2: f32 = S / 2.;
-    let top = Mesh::from(shape::Box::new(S, 0.01, S));
-    let side = Mesh::from(shape::Box::new(0.01, S, S));
-    let back = Mesh::from(shape::Box::new(S, S, 0.01));
-    let material = materials.add(StandardMaterial {
-      base_color: Color::rgba(0., 0., 0., 0.),
-      unlit: true,
-      ..default()
-    });
-
+  pub fn setup(commands: &mut ChildBuilder) {
     commands
-      .spawn()
-      // top
-      .insert_bundle(PbrBundle {
-        mesh: meshes.add(top.clone()),
-        transform: Transform::from_xyz(0.5, -0.5, 0.95),
-        material: material.clone(),
-        ..default()
-      })
-      // bottom
-      .with_children(|f| {
-        f.spawn_bundle(PbrBundle {
-          mesh: meshes.add(top),
-          transform: Transform::from_xyz(0., -S2, 0.),
-          material: material.clone(),
+      .spawn_bundle(SpotLightBundle {
+        spot_light: SpotLight {
+          range: RANGE,
+          intensity: INTENSITY,
+          outer_angle: OUTER_ANGLE,
+          inner_angle: INNER_ANGLE,
+          shadows_enabled: true,
           ..default()
-        });
-      })
-      // left
-      .with_children(|f| {
-        f.spawn_bundle(PbrBundle {
-          mesh: meshes.add(side.clone()),
-          transform: Transform::from_xyz(S2, 0., 0.),
-          material: material.clone(),
-          ..default()
-        });
-      })
-      // right
-      .with_children(|f| {
-        f.spawn_bundle(PbrBundle {
-          mesh: meshes.add(side),
-          transform: Transform::from_xyz(-S2, 0., 0.),
-          material: material.clone(),
-          ..default()
-        });
-      })
-      // back
-      .with_children(|f| {
-        f.spawn_bundle(PbrBundle {
-          mesh: meshes.add(back),
-          transform: Transform::from_xyz(0., 0., -S2),
-          material: material.clone(),
-          ..default()
-        });
+        },
+        // the player faces +z, aim the beam that way and a little toward the ground
+        transform: Transform::from_xyz(0.5, -0.5, 0.95)
+          .looking_at(Vec3::new(0.5, -2., 10.), Vec3::Y),
+        ..default()
       })
-      .with_children(|f| {
-        f.spawn_bundle(PointLightBundle {
-          point_light: PointLight {
-            range: 100.,
-            intensity: 10000.,
-            shadows_enabled: true,
-            ..Default::default()
-          },
-          transform: Transform::from_xyz(0., 0., 0.),
-          ..default()
-        });
+      .insert(Self {
+        on: true,
+        battery: 1.,
+        spares: 1,
       });
   }
+
+  pub fn update(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut query: Query<(&mut Self, &mut SpotLight, &mut Visibility)>,
+  ) {
+    for (mut flashlight, mut light, mut visibility) in &mut query {
+      if actions.just_pressed(Action::ToggleFlashlight) {
+        flashlight.toggle();
+      }
+
+      let dt = time.delta_seconds();
+      if flashlight.on {
+        flashlight.battery = (flashlight.battery - DRAIN * dt).max(0.);
+        if flashlight.battery <= 0. {
+          flashlight.on = false;
+        }
+      } else {
+        flashlight.battery = (flashlight.battery + RECHARGE * dt).min(1.);
+      }
+
+      visibility.is_visible = flashlight.on;
+      light.intensity = INTENSITY * flashlight.brightness();
+    }
+  }
+
+  /// Turns the light on or off. Turning on a dead flashlight swaps in a spare battery.
+  pub fn toggle(&mut self) {
+    if self.on {
+      self.on = false;
+      return;
+    }
+
+    if self.battery <= 0. && self.spares > 0 {
+      self.spares -= 1;
+      self.battery = 1.;
+    }
+    self.on = self.battery > 0.;
+  }
+
+  /// A spare battery, for when the loaded one runs flat.
+  pub fn add_battery(&mut self) {
+    self.spares += 1;
+  }
+
+  /// 0 to 1, dims and flickers as the battery runs low.
+  fn brightness(&self) -> f32 {
+    if self.battery >= LOW_CHARGE {
+      return 1.;
+    }
+
+    let dim = self.battery / LOW_CHARGE;
+    match thread_rng().gen_bool((1. - dim as f64) * 0.3) {
+      true => 0.,
+      false => 0.3 + dim * 0.7,
+    }
+  }
+
+  /// Whether `pos` is lit by this flashlight, given the light's global transform.
+  /// Ignores walls: callers that care should ray-cast as well.
+  pub fn illuminates(&self, transform: &GlobalTransform, pos: Vec3) -> bool {
+    if !self.on {
+      return false;
+    }
+
+    let to_pos = pos - transform.translation();
+    if to_pos.length() > RANGE {
+      return false;
+    }
+
+    // the beam is tilted toward the ground, compare directions on the ground plane
+    let forward = transform.forward();
+    let forward = Vec3::new(forward.x, 0., forward.z);
+    let to_pos = Vec3::new(to_pos.x, 0., to_pos.z);
+    if to_pos.length() <= f32::EPSILON {
+      return true;
+    }
+    forward.angle_between(to_pos) <= OUTER_ANGLE
+  }
+
+  /// Keeps the [`Lit`] marker on exactly the zombies inside a beam or a lit room. A zombie the
+  /// beam catches out of the dark is stunned for a moment.
+  pub fn light_zombies(
+    mut commands: Commands,
+    time: Res<Time>,
+    zones: Res<Zones>,
+    flashlights: Query<(&Self, &GlobalTransform)>,
+    mut zombies: Query<(Entity, &Transform, Option<&Lit>, &mut Zombie)>,
+  ) {
+    for (entity, t, lit, mut zombie) in &mut zombies {
+      let in_beam = flashlights
+        .iter()
+        .any(|(flashlight, ft)| flashlight.illuminates(ft, t.translation));
+      let in_light = in_beam
+        || zones
+          .cell_at(&t.translation)
+          .is_some_and(|cell| cell.room.is_lit());
+
+      match (in_light, lit.is_some()) {
+        (true, false) => {
+          commands.entity(entity).insert(Lit);
+          if in_beam {
+            zombie.stun(time.seconds_since_startup(), STUN);
+          }
+        }
+        (false, true) => {
+          commands.entity(entity).remove::<Lit>();
+        }
+        _ => {}
+      }
+    }
+  }
 }
+
+/// Marks a zombie that is currently caught in a flashlight beam or standing in a lit room. Lit
+/// zombies are drawn paler, so they stand out.
+#[derive(Component)]
+pub struct Lit;
@@ -0,0 +1,83 @@
+use crate::*;
+
+const PISTOL_MAGAZINE: u32 = 12;
+/// Seconds a reload takes.
+const RELOAD_TIME: f32 = 1.5;
+
+/// The gun the player is holding. There's always another magazine, only reloading takes time.
+#[derive(Component)]
+pub struct Weapon {
+  pub name: &'static str,
+  /// Rounds left in the magazine.
+  pub loaded: u32,
+  pub magazine: u32,
+  /// Seconds until the reload is done, 0 when not reloading.
+  reloading: f32,
+}
+
+impl Weapon {
+  pub fn pistol() -> Self {
+    Self {
+      name: "Pistol",
+      loaded: PISTOL_MAGAZINE,
+      magazine: PISTOL_MAGAZINE,
+      reloading: 0.,
+    }
+  }
+
+  pub fn is_reloading(&self) -> bool {
+    self.reloading > 0.
+  }
+
+  /// Seconds until the reload is done, 0 when not reloading.
+  pub fn reload_left(&self) -> f32 {
+    self.reloading
+  }
+
+  /// Picks up where a saved gun left off.
+  pub fn restore(&mut self, loaded: u32, reload_left: f32) {
+    self.loaded = loaded.min(self.magazine);
+    self.reloading = reload_left.clamp(0., RELOAD_TIME);
+  }
+
+  /// Uses up a round, if there's one loaded. Firing the last one starts a reload.
+  pub fn fire(&mut self) -> bool {
+    if self.is_reloading() || self.loaded == 0 {
+      return false;
+    }
+
+    self.loaded -= 1;
+    if self.loaded == 0 {
+      self.reload();
+    }
+    true
+  }
+
+  /// A full magazine right away, cancelling any reload.
+  pub fn refill(&mut self) {
+    self.loaded = self.magazine;
+    self.reloading = 0.;
+  }
+
+  fn reload(&mut self) {
+    if !self.is_reloading() && self.loaded < self.magazine {
+      self.reloading = RELOAD_TIME;
+    }
+  }
+
+  pub fn update(time: Res<Time>, actions: Res<Actions>, mut query: Query<&mut Self>) {
+    for mut weapon in &mut query {
+      if actions.just_pressed(Action::Reload) {
+        weapon.reload();
+      }
+
+      if weapon.is_reloading() {
+        weapon.reloading -= time.delta_seconds();
+        if weapon.reloading <= 0. {
+          weapon.reloading = 0.;
+          weapon.loaded = weapon.magazine;
+        }
+      }
+    }
+  }
+}
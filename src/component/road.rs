@@ -1,6 +1,7 @@
 use crate::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-const ROAD_WIDTH: f32 = 40.;
+pub const ROAD_WIDTH: f32 = 40.;
 const ROAD_WIDTH_2: f32 = ROAD_WIDTH / 2.;
 const ROAD_DEPTH: f32 = 0.2;
 const ROAD_LINE_LEN: f32 = 8.;
@@ -37,17 +38,15 @@ impl Road {
     child_builder: &mut ChildBuilder,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
     ass: &Res<AssetServer>,
   ) -> Entity {
-    let mesh = Mesh::from(shape::Box::new(ROAD_WIDTH, ROAD_DEPTH, self.len));
-    let material = materials.add(StandardMaterial {
-      base_color: Color::BLACK,
-      ..default()
-    });
+    let mesh = cache.mesh(meshes, MeshKey::cuboid(ROAD_WIDTH, ROAD_DEPTH, self.len));
+    let material = cache.material(materials, "road", Self::asphalt_material);
 
     child_builder
       .spawn_bundle(PbrBundle {
-        mesh: meshes.add(mesh),
+        mesh,
         material,
         transform: Transform::from_translation(self.translation).with_rotation(self.rotation),
         ..default()
@@ -57,13 +56,11 @@ impl Road {
 
         // white lines
         let mut z = ROAD_LINE_LEN_2;
-        let mesh = Mesh::from(shape::Box::new(ROAD_LINE_WIDTH, 0.1, ROAD_LINE_LEN));
-        let mesh = meshes.add(mesh);
-        let material = StandardMaterial {
+        let mesh = cache.mesh(meshes, MeshKey::cuboid(ROAD_LINE_WIDTH, 0.1, ROAD_LINE_LEN));
+        let material = cache.material(materials, "road/line", || StandardMaterial {
           base_color: Color::WHITE,
           ..default()
-        };
-        let material = materials.add(material);
+        });
         while z < self.len {
           child_builder.spawn_bundle(PbrBundle {
             mesh: mesh.clone(),
@@ -84,8 +81,8 @@ impl Road {
         }
 
         // sidewalks
-        let mesh = meshes.add(Mesh::from(shape::Box::new(SIDEWALK_WIDTH, 0.1, self.len)));
-        let material = materials.add(StandardMaterial {
+        let mesh = cache.mesh(meshes, MeshKey::cuboid(SIDEWALK_WIDTH, 0.1, self.len));
+        let material = cache.material(materials, "road/sidewalk", || StandardMaterial {
           base_color: Color::GRAY,
           ..default()
         });
@@ -104,10 +101,21 @@ impl Road {
       })
       .id()
   }
+
+  fn asphalt_material() -> StandardMaterial {
+    StandardMaterial {
+      base_color: Color::BLACK,
+      ..default()
+    }
+  }
 }
 
 struct StreetLamp;
 
+/// The light of a street lamp, only on when it's dark.
+#[derive(Component)]
+pub struct StreetLampLight;
+
 impl StreetLamp {
   pub fn fabricate(
     transform: Transform,
@@ -122,15 +130,17 @@ impl StreetLamp {
         ..default()
       })
       .with_children(|child_builder| {
-        child_builder.spawn_bundle(PointLightBundle {
-          point_light: PointLight {
-            range: 40.,
-            intensity: 10000.,
+        child_builder
+          .spawn_bundle(PointLightBundle {
+            point_light: PointLight {
+              range: 40.,
+              intensity: 10000.,
+              ..default()
+            },
+            transform: Transform::from_xyz(0., 15., 0.),
             ..default()
-          },
-          transform: Transform::from_xyz(0., 15., 0.),
-          ..default()
-        });
+          })
+          .insert(StreetLampLight);
       })
       .id()
   }
@@ -138,79 +148,446 @@ impl StreetLamp {
 
 pub const GRID_SIZE: f32 = 800.;
 pub const GRID_SIZE_2: f32 = GRID_SIZE / 2.;
-static WAIT: Duration = Duration::from_secs(5);
+/// Cells are centered on multiples of `GRID_SIZE`.
+pub const GRID: Grid = Grid::centered(GRID_SIZE);
+
+/// Building lots along a road, measured along the road.
+const LOT_WIDTH: f32 = 230.;
+const LOT_WIDTH_2: f32 = LOT_WIDTH / 2.;
+const LOTS_PER_ROAD: usize = 3;
+/// Gap between the sidewalk and the front of a building, for its front yard.
+const LOT_SETBACK: f32 = 30.;
+
+#[derive(Default)]
 pub struct RoadGrid {
   pub grid: HashMap<Coord, RoadCell>,
-  last_ran: Instant,
-}
-impl Default for RoadGrid {
-  fn default() -> Self {
-    Self {
-      grid: HashMap::new(),
-      last_ran: Instant::now() - WAIT,
-    }
-  }
 }
 
 impl RoadGrid {
   #[inline]
-  fn coord(t: &Transform) -> Coord {
-    Coord {
-      z: (t.translation.z / GRID_SIZE) as i16,
-      x: (t.translation.x / GRID_SIZE) as i16,
-    }
+  pub fn coord(pos: &Vec3) -> Coord {
+    GRID.coord(pos)
   }
-  pub fn update(
-    mut grid: ResMut<Self>,
-    query: Query<&Transform, With<Player>>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    ass: Res<AssetServer>,
-  ) {
-    if grid.last_ran.elapsed() < WAIT || query.is_empty() {
-      return;
-    }
 
-    let coord = Self::coord(query.single());
-    grid
+  #[inline]
+  pub fn center(coord: Coord) -> Vec3 {
+    GRID.center(coord)
+  }
+
+  /// Spawns the roads for a grid cell, unless they're already there.
+  pub fn generate(
+    &mut self,
+    coord: Coord,
+    seed: u64,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
+  ) {
+    self
       .grid
       .entry(coord)
-      .or_insert_with(|| RoadCell::new(coord, &mut commands, &mut meshes, &mut materials, &ass));
+      .or_insert_with(|| RoadCell::new(coord, seed, commands, meshes, materials, cache, ass));
+  }
+
+  /// Despawns the roads of a grid cell.
+  pub fn remove(&mut self, coord: Coord, commands: &mut Commands) {
+    if let Some(cell) = self.grid.remove(&coord) {
+      commands.entity(cell.entity).despawn_recursive();
+    }
+  }
+
+  /// Where buildings can go along the roads leaving the min corner of a cell, with their entrance
+  /// facing the road. Buildings are laid out along their local +x, away from the entrance.
+  pub fn lots(seed: u64, coord: Coord) -> Vec<Transform> {
+    let corner = GRID.min(coord);
+    let setback = ROAD_WIDTH_2 + SIDEWALK_WIDTH + CELL_SIZE_2 + LOT_SETBACK;
+    let mut lots = vec![];
+
+    for (axis, skip) in [(Axis::Z, 0), (Axis::X, 1)] {
+      let len = match Edge::at(seed, coord, axis).len() {
+        Some(len) => len,
+        None => continue,
+      };
+
+      // the first lot along x would be in the yard of the first one along z
+      for i in skip..LOTS_PER_ROAD {
+        let along = ROAD_WIDTH + LOT_WIDTH_2 + i as f32 * LOT_WIDTH;
+        if along + LOT_WIDTH_2 > len {
+          break;
+        }
+
+        lots.push(match axis {
+          Axis::Z => Transform::from_xyz(corner.x + setback, 0.1, corner.z + along),
+          // turned so the building's +x points away from the road, along +z
+          Axis::X => Transform::from_xyz(corner.x + along, 0.1, corner.z + setback)
+            .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+        });
+      }
+    }
+    lots
+  }
+
+  /// Where the roads leaving the min corner of a cell run, from the corner to where they end.
+  pub fn roads(seed: u64, coord: Coord) -> Vec<(Vec3, Vec3)> {
+    let corner = GRID.min(coord);
+    [(Axis::X, Vec3::X), (Axis::Z, Vec3::Z)]
+      .into_iter()
+      .filter_map(|(axis, dir)| {
+        let len = Edge::at(seed, coord, axis).len()?;
+        Some((corner, corner + dir * len))
+      })
+      .collect()
+  }
+
+  /// A random number generator that only depends on the seed, cell and what it's used for.
+  pub fn cell_rng(seed: u64, coord: Coord, salt: Salt) -> StdRng {
+    StdRng::seed_from_u64(
+      seed ^ (coord.x as u16 as u64) << 16 ^ (coord.z as u16 as u64) << 32 ^ (salt as u64) << 48,
+    )
+  }
+}
+
+/// What runs along one side of a grid cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+  None,
+  /// Runs halfway and ends in a cul-de-sac.
+  DeadEnd,
+  Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+  X,
+  Z,
+}
+
+impl Edge {
+  /// The road leaving the min corner of the cell at `coord` along `axis`. Only depends on the seed
+  /// and coordinate, so neighbouring cells can work out their corners without being generated.
+  fn at(seed: u64, coord: Coord, axis: Axis) -> Self {
+    let salt = match axis {
+      Axis::X => Salt::RoadX,
+      Axis::Z => Salt::RoadZ,
+    };
+    match RoadGrid::cell_rng(seed, coord, salt).gen_range(0..20) {
+      0..=2 => Self::None,
+      3..=5 => Self::DeadEnd,
+      _ => Self::Full,
+    }
+  }
 
-    grid.last_ran = Instant::now();
+  /// How far the road runs from the corner, if there is one.
+  fn len(self) -> Option<f32> {
+    match self {
+      Self::None => None,
+      Self::DeadEnd => Some(GRID_SIZE_2),
+      Self::Full => Some(GRID_SIZE - ROAD_WIDTH_2),
+    }
   }
 }
 
+/// A grid cell owns the roads leaving its min corner along +x and +z, and the piece joining them
+/// at that corner.
 pub struct RoadCell {
+  pub entity: Entity,
   pub roads: HashSet<Entity>,
 }
 
 impl RoadCell {
   fn new(
     coord: Coord,
+    seed: u64,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
     ass: &Res<AssetServer>,
   ) -> Self {
-    let translation = Vec3::new(coord.x as f32 * GRID_SIZE, 0., coord.z as f32 * GRID_SIZE);
+    let corner = GRID.min(coord);
+
+    let x_edge = Edge::at(seed, coord, Axis::X);
+    let z_edge = Edge::at(seed, coord, Axis::Z);
 
     let mut roads = HashSet::new();
-    commands
+    let entity = commands
       .spawn_bundle(PbrBundle {
-        transform: Transform::from_translation(translation),
+        transform: Transform::from_translation(corner),
         ..default()
       })
       .with_children(|child_builder| {
-        let road = Road::build(
-          translation + Vec3::new(-GRID_SIZE_2, 0., -GRID_SIZE_2),
-          translation + Vec3::new(-GRID_SIZE_2, 0., GRID_SIZE_2),
-        )
-        .fabricate(child_builder, meshes, materials, ass);
-        roads.insert(road);
-      });
-
-    Self { roads }
+        for (edge, dir) in [(x_edge, Vec3::X), (z_edge, Vec3::Z)] {
+          let len = match edge.len() {
+            Some(len) => len,
+            None => continue,
+          };
+
+          // leave room for the corner piece
+          let road = Road::build(dir * ROAD_WIDTH_2, dir * len).fabricate(
+            child_builder,
+            meshes,
+            materials,
+            cache,
+            ass,
+          );
+          roads.insert(road);
+
+          if edge == Edge::DeadEnd {
+            let cul_de_sac =
+              Junction::CulDeSac.fabricate(dir * len, child_builder, meshes, materials, cache);
+            roads.insert(cul_de_sac);
+          }
+        }
+
+        if let Some(junction) = Junction::at(seed, coord) {
+          roads.insert(junction.fabricate(Vec3::ZERO, child_builder, meshes, materials, cache));
+        }
+      })
+      .id();
+
+    Self { entity, roads }
+  }
+}
+
+/// The piece of road where roads meet or end.
+#[derive(Debug, PartialEq)]
+enum Junction {
+  Intersection,
+  CulDeSac,
+}
+
+impl Junction {
+  /// The piece at the min corner of the cell at `coord`, if any roads reach it. The cell's own
+  /// roads start there, but a dead end coming in from a neighbouring cell stops halfway.
+  fn at(seed: u64, coord: Coord) -> Option<Self> {
+    let own = [
+      Edge::at(seed, coord, Axis::X),
+      Edge::at(seed, coord, Axis::Z),
+    ]
+    .into_iter()
+    .filter(|e| *e != Edge::None)
+    .count();
+    let incoming = [
+      Edge::at(
+        seed,
+        Coord {
+          x: coord.x - 1,
+          ..coord
+        },
+        Axis::X,
+      ),
+      Edge::at(
+        seed,
+        Coord {
+          z: coord.z - 1,
+          ..coord
+        },
+        Axis::Z,
+      ),
+    ]
+    .into_iter()
+    .filter(|e| *e == Edge::Full)
+    .count();
+
+    match own + incoming {
+      0 => None,
+      1 => Some(Self::CulDeSac),
+      _ => Some(Self::Intersection),
+    }
+  }
+
+  fn fabricate(
+    self,
+    translation: Vec3,
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+  ) -> Entity {
+    let material = cache.material(materials, "road", Road::asphalt_material);
+
+    let (mesh, scale) = match self {
+      Self::Intersection => (
+        cache.mesh(meshes, MeshKey::cuboid(ROAD_WIDTH, ROAD_DEPTH, ROAD_WIDTH)),
+        Vec3::ONE,
+      ),
+      // a sphere squashed flat
+      Self::CulDeSac => (
+        cache.mesh(meshes, MeshKey::uv_sphere(ROAD_WIDTH, 32, 8)),
+        Vec3::new(1., ROAD_DEPTH / (ROAD_WIDTH * 2.), 1.),
+      ),
+    };
+
+    child_builder
+      .spawn_bundle(PbrBundle {
+        mesh,
+        material,
+        transform: Transform::from_translation(translation + Vec3::Y * 0.1).with_scale(scale),
+        ..default()
+      })
+      .id()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SEEDS: u64 = 200;
+  const CELLS: i16 = 10;
+
+  /// Counts the roads that actually end at the min corner of a cell by where they run, rather than
+  /// by their edges.
+  fn roads_reaching(seed: u64, coord: Coord) -> usize {
+    let corner = GRID.min(coord);
+    [
+      coord,
+      Coord {
+        x: coord.x - 1,
+        ..coord
+      },
+      Coord {
+        z: coord.z - 1,
+        ..coord
+      },
+    ]
+    .into_iter()
+    .flat_map(|coord| RoadGrid::roads(seed, coord))
+    // roads stop short of the junction they run into
+    .filter(|(from, to)| from.distance(corner) < 1. || to.distance(corner) <= ROAD_WIDTH_2 + 1.)
+    .count()
+  }
+
+  /// The min corner of the cell at `(x, z)`.
+  fn corner(x: i16, z: i16) -> Vec3 {
+    GRID.min(Coord { x, z })
+  }
+
+  #[test]
+  fn fixed_seeds_lay_out_known_roads() {
+    let full = GRID_SIZE - ROAD_WIDTH_2;
+
+    // the end of a road coming in along x, and nothing else
+    assert!(RoadGrid::roads(0, Coord { x: 2, z: -3 }).is_empty());
+    assert_eq!(
+      RoadGrid::roads(0, Coord { x: 1, z: -3 }),
+      vec![
+        (corner(1, -3), corner(1, -3) + Vec3::X * full),
+        (corner(1, -3), corner(1, -3) + Vec3::Z * full)
+      ]
+    );
+    assert!(RoadGrid::roads(0, Coord { x: 2, z: -4 })
+      .iter()
+      .all(|(from, to)| from.z == to.z));
+    assert_eq!(
+      Junction::at(0, Coord { x: 2, z: -3 }),
+      Some(Junction::CulDeSac)
+    );
+
+    // a dead end leaving a corner nothing else reaches
+    assert_eq!(
+      RoadGrid::roads(0, Coord { x: -3, z: -2 }),
+      vec![(corner(-3, -2), corner(-3, -2) + Vec3::Z * GRID_SIZE_2)]
+    );
+    assert!(RoadGrid::roads(0, Coord { x: -4, z: -2 })
+      .iter()
+      .all(|(from, to)| from.x == to.x));
+    assert!(RoadGrid::roads(0, Coord { x: -3, z: -3 })
+      .iter()
+      .all(|(from, to)| from.z == to.z));
+    assert_eq!(
+      Junction::at(0, Coord { x: -3, z: -2 }),
+      Some(Junction::CulDeSac)
+    );
+
+    // a road bending from x to z, with a dead end from -z stopping short of it
+    assert_eq!(
+      RoadGrid::roads(1, Coord { x: 0, z: -1 }),
+      vec![(corner(0, -1), corner(0, -1) + Vec3::Z * full)]
+    );
+    assert_eq!(
+      RoadGrid::roads(1, Coord { x: -1, z: -1 }),
+      vec![
+        (corner(-1, -1), corner(-1, -1) + Vec3::X * full),
+        (corner(-1, -1), corner(-1, -1) + Vec3::Z * full)
+      ]
+    );
+    assert_eq!(
+      RoadGrid::roads(1, Coord { x: 0, z: -2 }),
+      vec![
+        (corner(0, -2), corner(0, -2) + Vec3::X * full),
+        (corner(0, -2), corner(0, -2) + Vec3::Z * GRID_SIZE_2)
+      ]
+    );
+    assert_eq!(
+      Junction::at(1, Coord { x: 0, z: -1 }),
+      Some(Junction::Intersection)
+    );
+  }
+
+  #[test]
+  fn junctions_fit_the_roads_reaching_them() {
+    for seed in 0..SEEDS {
+      for x in -CELLS..CELLS {
+        for z in -CELLS..CELLS {
+          let coord = Coord { x, z };
+          let roads = roads_reaching(seed, coord);
+          match Junction::at(seed, coord) {
+            None => assert_eq!(roads, 0, "seed {} at {:?}", seed, coord),
+            Some(Junction::CulDeSac) => assert_eq!(roads, 1, "seed {} at {:?}", seed, coord),
+            // two roads meeting at a bend need the corner piece as much as a crossing does
+            Some(Junction::Intersection) => assert!(roads >= 2, "seed {} at {:?}", seed, coord),
+          }
+          if roads >= 3 {
+            assert_eq!(
+              Junction::at(seed, coord),
+              Some(Junction::Intersection),
+              "seed {} at {:?}",
+              seed,
+              coord
+            );
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn neighbours_agree_on_shared_edges() {
+    for seed in 0..SEEDS {
+      for x in -CELLS..CELLS {
+        for z in -CELLS..CELLS {
+          for (from, to) in RoadGrid::roads(seed, Coord { x, z }) {
+            let dir = (to - from).normalize();
+            let next = match dir.x > 0. {
+              true => Coord { x: x + 1, z },
+              false => Coord { x, z: z + 1 },
+            };
+            match from.distance(to) {
+              // runs into the junction of the next corner along
+              len if len > GRID_SIZE_2 => {
+                assert!(
+                  (to + dir * ROAD_WIDTH_2).distance(GRID.min(next)) < 0.01,
+                  "seed {} at {:?}: road to {} misses the corner",
+                  seed,
+                  (x, z),
+                  to
+                );
+                assert!(
+                  Junction::at(seed, next).is_some(),
+                  "seed {} at {:?}: road runs into nothing",
+                  seed,
+                  next
+                );
+              }
+              // stops in its own cul-de-sac halfway, short of either junction
+              len => assert_eq!(len, GRID_SIZE_2, "seed {} at {:?}", seed, (x, z)),
+            }
+          }
+        }
+      }
+    }
   }
 }
@@ -1,49 +1,83 @@
 use crate::*;
-use bevy::{
-  pbr::RenderMaterials,
-  reflect::TypeUuid,
-  render::{
-    render_resource::{encase, AsBindGroup, OwnedBindingResource, ShaderRef, ShaderType},
-    renderer::RenderQueue,
-    Extract,
-  },
-};
-use rand::thread_rng;
+use rand::{rngs::StdRng, SeedableRng};
+
+pub mod render;
+pub use render::{ZombieMaterial, ZombieRenderPlugin};
 
 #[derive(Component)]
 pub struct Zombie {
   dest: Vec3,
   nav: Vec<Arc<NavNode>>,
-  debug_square: Option<Entity>,
-  nav_timeout: Instant,
-  achievement_timeout: Instant,
-  stunned_until: Option<Instant>,
+  /// Game seconds until it next wanders or asks for a path.
+  nav_timeout: f64,
+  stunned_until: Option<f64>,
 }
 
-#[derive(AsBindGroup, TypeUuid, Clone)]
-#[uuid = "cc8705a9-9189-43e5-8a5b-c28b57bd6234"]
-pub struct ZombieMaterial {
-  #[uniform(0)]
-  color: Color,
+#[derive(Component)]
+pub struct Aggressive;
+
+/// Zombies the player has killed in this world.
+#[derive(Default)]
+pub struct Kills(pub u32);
+
+impl Kills {
+  pub fn reset(mut this: ResMut<Self>) {
+    this.0 = 0;
+  }
 }
 
-impl Material for ZombieMaterial {
-  fn fragment_shader() -> ShaderRef {
-    "shaders/zombie.wgsl".into()
+/// What zombies decide at random, from the world seed so the same world plays out the same.
+pub struct AiRng(pub StdRng);
+
+impl AiRng {
+  pub fn new(seed: u64) -> Self {
+    Self(StdRng::seed_from_u64(seed ^ Salt::Ai as u64))
+  }
+
+  pub fn reset(mut this: ResMut<Self>, seed: Res<WorldSeed>) {
+    *this = Self::new(seed.0);
   }
 }
 
-#[derive(Clone, ShaderType)]
-struct ZombieMaterialUniformData {
-  color: Color,
+static ZOMBIE_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Most game seconds a zombie keeps heading somewhere before wandering off or looking again.
+const NAV_TIMEOUT: f64 = 3.;
+/// Most zombies alive at once, changed with `set zombie_limit`.
+static ZOMBIE_LIMIT: AtomicUsize = AtomicUsize::new(50);
+/// How far from the player `spawn zombie` puts them.
+const SPAWN_SPREAD: f32 = 20.;
+/// How close the player has to be for an aggressive zombie to notice them, in daylight.
+const SIGHT: f32 = 150.;
+/// How close to a noise a zombie has to be to hear it, in clear weather.
+const HEARING: f32 = 300.;
+/// How much further zombies see the player when the player is in a lit room.
+const LIT_SIGHT: f32 = 1.5;
+/// How many game seconds a noise draws zombies to the player for.
+const NOISE_MEMORY: f64 = 2.;
+
+/// The last noise the player made that zombies can hear, like a gunshot.
+#[derive(Default)]
+pub struct Noise {
+  pub pos: Vec3,
+  /// In game seconds.
+  pub at: Option<f64>,
 }
 
-#[derive(Component)]
-pub struct Aggressive;
+impl Noise {
+  pub fn make(&mut self, pos: Vec3, time: &Time) {
+    self.pos = pos;
+    self.at = Some(time.seconds_since_startup());
+  }
 
-static ZOMBIE_COUNT: AtomicUsize = AtomicUsize::new(0);
-static NAV_TIMEOUT: Duration = Duration::from_secs(3);
-const ZOMBIE_LIMIT: usize = 50;
+  /// Whether a zombie at `pos` can hear the noise, `hearing` being how far it can hear.
+  fn heard(&self, pos: Vec3, hearing: f32, now: f64) -> bool {
+    self.at.is_some_and(|at| now - at < NOISE_MEMORY) && self.pos.distance(pos) <= hearing
+  }
+
+  pub fn reset(mut this: ResMut<Self>) {
+    *this = Self::default();
+  }
+}
 
 const SIZE: f32 = 2.;
 const SIZE_2: f32 = SIZE / 2.;
@@ -53,9 +87,10 @@ impl Zombie {
     pos: Vec3,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
+    cache: &mut ResMut<AssetCache>,
     materials: &mut ResMut<Assets<ZombieMaterial>>,
   ) -> Option<Entity> {
-    if ZOMBIE_COUNT.load(Ordering::SeqCst) >= ZOMBIE_LIMIT {
+    if ZOMBIE_COUNT.load(Ordering::SeqCst) >= ZOMBIE_LIMIT.load(Ordering::SeqCst) {
       return None;
     }
 
@@ -63,7 +98,8 @@ impl Zombie {
 
     let id = commands
       .spawn_bundle(MaterialMeshBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: SIZE })),
+        mesh: cache.mesh(meshes, MeshKey::cube(SIZE)),
+        // every zombie has its own, for its health to show in
         material: materials.add(ZombieMaterial {
           color: Color::rgb(health.health(), 0., 0.),
         }),
@@ -80,14 +116,7 @@ impl Zombie {
       .insert(Velocity::default())
       .insert(health)
       .insert(Aggressive)
-      .insert(Zombie {
-        dest: pos,
-        nav: vec![],
-        debug_square: None,
-        stunned_until: None,
-        nav_timeout: Instant::now(),
-        achievement_timeout: Instant::now() + NAV_TIMEOUT,
-      })
+      .insert(Zombie::resumed(pos, None))
       .id();
 
     ZOMBIE_COUNT.fetch_add(1, Ordering::SeqCst);
@@ -95,6 +124,60 @@ impl Zombie {
     Some(id)
   }
 
+  /// Despawns a zombie, making room for another one under the limit.
+  pub fn despawn(commands: &mut Commands, entity: Entity) {
+    commands.entity(entity).despawn_recursive();
+    ZOMBIE_COUNT.fetch_sub(1, Ordering::SeqCst);
+  }
+
+  /// A zombie picking up where a saved one left off.
+  pub fn resumed(dest: Vec3, stunned_until: Option<f64>) -> Self {
+    Self {
+      dest,
+      nav: vec![],
+      stunned_until,
+      nav_timeout: 0.,
+    }
+  }
+
+  /// Stops it in its tracks for `seconds` from `now`, unless it's stunned for longer already.
+  pub fn stun(&mut self, now: f64, seconds: f64) {
+    let until = now + seconds;
+    if self.stunned_until.is_none_or(|current| current < until) {
+      self.stunned_until = Some(until);
+    }
+  }
+
+  /// The game time it's stunned until, if it is.
+  pub fn stunned_until(&self) -> Option<f64> {
+    self.stunned_until
+  }
+
+  /// Where the zombie is heading right now.
+  pub fn dest(&self) -> Vec3 {
+    self.dest
+  }
+
+  /// The rest of the path it's following, the next node last.
+  pub fn nav(&self) -> &[Arc<NavNode>] {
+    &self.nav
+  }
+
+  /// How many zombies there are.
+  pub fn count() -> usize {
+    ZOMBIE_COUNT.load(Ordering::SeqCst)
+  }
+
+  /// Whether another zombie would fit under the limit.
+  pub fn has_room() -> bool {
+    ZOMBIE_COUNT.load(Ordering::SeqCst) < ZOMBIE_LIMIT.load(Ordering::SeqCst)
+  }
+
+  /// Forget about every spawned zombie, used when the world is torn down.
+  pub fn reset_count() {
+    ZOMBIE_COUNT.store(0, Ordering::SeqCst);
+  }
+
   fn material() -> StandardMaterial {
     StandardMaterial {
       base_color: Color::RED,
@@ -105,150 +188,236 @@ impl Zombie {
 
   pub fn update_normal(
     zones: Res<Zones>,
+    time: Res<Time>,
+    mut rng: ResMut<AiRng>,
     mut query: Query<(&Transform, &mut ExternalForce, &mut Self), Without<Aggressive>>,
-    player_query: Query<&Transform, With<Player>>,
   ) {
+    let now = time.seconds_since_startup();
     for (t, mut ef, mut z) in &mut query {
-      z.travel(t, &mut ef, &zones);
+      z.travel(t, &mut ef, &zones, now, &mut rng.0);
     }
   }
 
   pub fn update_aggressive(
     zones: Res<Zones>,
-    mut query: Query<(&Transform, &mut ExternalForce, &mut Self), With<Aggressive>>,
+    mut path_queue: ResMut<PathQueue>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    noise: Res<Noise>,
+    time: Res<Time>,
+    mut rng: ResMut<AiRng>,
+    mut query: Query<(Entity, &Transform, &mut ExternalForce, &mut Self), With<Aggressive>>,
     player_query: Query<&Transform, With<Player>>,
   ) {
-    let now = Instant::now();
+    let now = time.seconds_since_startup();
     if player_query.is_empty() {
       return;
     }
     let player_transform = player_query.single();
+    let mut sight = SIGHT * time_of_day.zombie_sight() * weather.zombie_sight();
+    // the player stands out in a lit room
+    if zones
+      .cell_at(&player_transform.translation)
+      .is_some_and(|cell| cell.room.is_lit())
+    {
+      sight *= LIT_SIGHT;
+    }
+    let hearing = HEARING * weather.hearing();
 
-    for (t, mut ef, mut z) in &mut query {
+    for (entity, t, mut ef, mut z) in &mut query {
       // stun
       if let Some(stunned_until) = z.stunned_until {
         if stunned_until > now {
+          // stands still rather than pushing on the way it was going
+          ef.force = Vec3::ZERO;
           continue;
         }
 
         z.stunned_until = None;
       }
 
-      z.create_path_to_player(t, player_transform, &zones);
-      z.travel(t, &mut ef, &zones);
+      if t.translation.distance(player_transform.translation) <= sight
+        || noise.heard(t.translation, hearing, now)
+      {
+        z.request_path(entity, &mut path_queue, now, &mut rng.0);
+      }
+      z.travel(t, &mut ef, &zones, now, &mut rng.0);
     }
   }
 
-  pub fn update_impact(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Velocity, &Impact, &mut Health)>,
+  /// Works out the paths to the player that are up next in the `PathQueue`.
+  pub fn find_paths(
+    mut path_queue: ResMut<PathQueue>,
+    nav_graph: NavGraph,
+    player_query: Query<&Transform, With<Player>>,
+    mut query: Query<(&Transform, &mut Self)>,
   ) {
-    for (entity, mut velocity, impact, mut health) in query.iter_mut() {
-      velocity.linvel = impact.force;
-      health.damage(impact.damage);
-
-      if health.is_dead() {
-        commands.entity(entity).despawn_recursive();
-        continue;
+    let player_transform = match player_query.iter().next() {
+      Some(t) => t,
+      None => return,
+    };
+
+    for entity in path_queue.take() {
+      // it may have died while waiting
+      if let Ok((t, mut z)) = query.get_mut(entity) {
+        let mut path = nav_graph.path(&t.translation, &player_transform.translation);
+        path.reverse();
+        z.nav = path;
       }
-
-      commands.entity(entity).remove::<Impact>();
     }
   }
 
-  pub fn prepare_health(
-    materials: Res<RenderMaterials<ZombieMaterial>>,
-    mut health_query: Query<(&mut Health, &Handle<ZombieMaterial>)>,
-    render_queue: Res<RenderQueue>,
+  pub fn update_impact(
+    mut commands: Commands,
+    mut kills: ResMut<Kills>,
+    mut query: Query<(Entity, &mut Velocity, &Impact, &mut Health, Option<&Zombie>)>,
   ) {
-    for (mut health, handle) in &mut health_query {
-      if !health.reset_changed() {
-        continue;
-      }
+    for (entity, mut velocity, impact, mut health, zombie) in query.iter_mut() {
+      velocity.linvel = impact.force;
+      health.damage(impact.damage);
 
-      if let Some(material) = materials.get(handle) {
-        for binding in material.bindings.iter() {
-          if let OwnedBindingResource::Buffer(cur_buffer) = binding {
-            let mut buffer = encase::UniformBuffer::new(Vec::new());
-            buffer
-              .write(&ZombieMaterialUniformData {
-                color: health.color(),
-              })
-              .unwrap();
-            render_queue.write_buffer(cur_buffer, 0, buffer.as_ref());
+      if health.is_dead() {
+        match zombie {
+          Some(_) => {
+            Self::despawn(&mut commands, entity);
+            kills.0 += 1;
           }
+          None => commands.entity(entity).despawn_recursive(),
         }
+        continue;
       }
-    }
-  }
 
-  pub fn extract_health(
-    mut commands: Commands,
-    health_query: Extract<Query<(Entity, &Health, &Handle<ZombieMaterial>)>>,
-  ) {
-    for (entity, health, handle) in health_query.iter() {
-      commands
-        .get_or_spawn(entity)
-        .insert(*health)
-        .insert(handle.clone());
+      commands.entity(entity).remove::<Impact>();
     }
   }
 
-  fn wander(&mut self, t: &Transform, zones: &Res<Zones>) {
-    if self.nav_timeout > Instant::now() {
+  fn wander(&mut self, t: &Transform, zones: &Res<Zones>, now: f64, rng: &mut StdRng) {
+    if self.nav_timeout > now {
       return;
     }
-    self.reset_timer();
+    self.reset_timer(now, rng);
 
     if let Some(cell) = zones.cell_at(&t.translation) {
-      self.dest = cell.random_pos();
+      self.dest = cell.random_pos(rng);
     } else {
-      let mut rng = thread_rng();
       let var = 10.;
       self.dest = t.translation + Vec3::new(rng.gen_range(-var..var), 0., rng.gen_range(-var..var));
     }
   }
 
-  fn reset_timer(&mut self) {
-    self.nav_timeout =
-      Instant::now() + Duration::from_millis(rand::thread_rng().gen_range(0..3000));
+  fn reset_timer(&mut self, now: f64, rng: &mut StdRng) {
+    self.nav_timeout = now + rng.gen_range(0.0..NAV_TIMEOUT);
   }
 
-  fn travel(&mut self, t: &Transform, ef: &mut ExternalForce, zones: &Res<Zones>) {
+  fn travel(
+    &mut self,
+    t: &Transform,
+    ef: &mut ExternalForce,
+    zones: &Res<Zones>,
+    now: f64,
+    rng: &mut StdRng,
+  ) {
     if let Some(dest) = self.nav.last() {
       if dest.area.contains(&t.translation) {
         self.nav.pop();
         if let Some(dest) = self.nav.last() {
           self.dest = dest.pos;
-          self.reset_timer();
+          self.reset_timer(now, rng);
         }
 
         return;
       }
     } else if self.nav.is_empty() {
-      self.wander(t, zones);
+      self.wander(t, zones, now, rng);
     }
 
     ef.force = (self.dest - t.translation).normalize() * 6000.;
   }
 
-  fn create_path_to_player(&mut self, t: &Transform, pt: &Transform, zones: &Res<Zones>) {
-    if self.nav_timeout > Instant::now() {
+  fn request_path(
+    &mut self,
+    entity: Entity,
+    path_queue: &mut PathQueue,
+    now: f64,
+    rng: &mut StdRng,
+  ) {
+    if self.nav_timeout > now {
       return;
     }
-    self.reset_timer();
+    self.reset_timer(now, rng);
+    path_queue.request(entity);
+  }
+}
 
-    if let Some(zombie_cell) = zones.cell_at(&t.translation) {
-      // check if player is in same building
-      if let Some(player_cell) = zombie_cell.building.pos_global_to_cell(&pt.translation) {
-        let mut nav = Navigator::new(&zombie_cell.nav_node(), &player_cell.nav_node());
-        nav.nav();
-        nav.path.reverse();
-        self.nav = nav.path;
-        return;
+impl ConsoleCommands for Zombie {
+  fn register(console: &mut Console) {
+    console.command(
+      "spawn",
+      "spawn zombie [n]",
+      "Spawns zombies around the player",
+      Self::spawn_command,
+    );
+    console.var(
+      "zombie_limit",
+      "Most zombies alive at once",
+      |_| ZOMBIE_LIMIT.load(Ordering::SeqCst).to_string(),
+      |_, value| {
+        let limit = value.parse().map_err(|_| "Not a number".to_string())?;
+        ZOMBIE_LIMIT.store(limit, Ordering::SeqCst);
+        Ok(())
+      },
+    );
+  }
+}
+
+impl Zombie {
+  // spelling out the SystemState here keeps what the command touches next to where it's used
+  #[allow(clippy::type_complexity)]
+  fn spawn_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let usage = "spawn zombie [n]";
+    if args.first() != Some(&"zombie") {
+      return Err(format!("Usage: {}", usage));
+    }
+    let n = match args.get(1) {
+      Some(_) => console::arg(args, 1, usage)?,
+      None => 1,
+    };
+
+    let mut state: SystemState<(
+      Commands,
+      ResMut<Assets<Mesh>>,
+      ResMut<AssetCache>,
+      ResMut<Assets<ZombieMaterial>>,
+      Query<&Transform, With<Player>>,
+    )> = SystemState::new(world);
+    let (mut commands, mut meshes, mut cache, mut materials, player_query) = state.get_mut(world);
+    let center = player_query
+      .iter()
+      .next()
+      .map(|t| t.translation)
+      .ok_or("No player")?;
+
+    let mut rng = rand::thread_rng();
+    let mut spawned = 0;
+    for _ in 0..n {
+      let offset = Vec3::new(
+        rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD),
+        0.,
+        rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD),
+      );
+      match Self::fabricate(
+        center + offset,
+        &mut commands,
+        &mut meshes,
+        &mut cache,
+        &mut materials,
+      ) {
+        Some(_) => spawned += 1,
+        None => break,
       }
     }
+    state.apply(world);
 
-    self.nav.clear();
+    Ok(format!("Spawned {} zombies", spawned))
   }
 }
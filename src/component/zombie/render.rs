@@ -0,0 +1,114 @@
+use crate::*;
+use bevy::{
+  pbr::RenderMaterials,
+  reflect::TypeUuid,
+  render::{
+    render_resource::{encase, AsBindGroup, OwnedBindingResource, ShaderRef, ShaderType},
+    renderer::RenderQueue,
+    Extract, RenderApp, RenderStage,
+  },
+};
+
+/// How far a zombie's color is lifted towards white while it's [`Lit`], so it stands out.
+const LIT_GLOW: f32 = 0.35;
+
+/// Shows each zombie's health in its color, paler while it's lit. Only for rendering, zombies work
+/// without it.
+pub struct ZombieRenderPlugin;
+
+type ExtractedZombies<'w, 's> = Query<
+  'w,
+  's,
+  (
+    Entity,
+    &'static Health,
+    &'static Handle<ZombieMaterial>,
+    Option<ChangeTrackers<Lit>>,
+  ),
+>;
+
+/// Whether an extracted zombie is lit, and whether that changed since the last frame.
+#[derive(Component, Clone, Copy)]
+struct LitState {
+  lit: bool,
+  changed: bool,
+}
+
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "cc8705a9-9189-43e5-8a5b-c28b57bd6234"]
+pub struct ZombieMaterial {
+  #[uniform(0)]
+  pub color: Color,
+}
+
+impl Material for ZombieMaterial {
+  fn fragment_shader() -> ShaderRef {
+    "shaders/zombie.wgsl".into()
+  }
+}
+
+#[derive(Clone, ShaderType)]
+struct ZombieMaterialUniformData {
+  color: Color,
+}
+
+impl Plugin for ZombieRenderPlugin {
+  fn build(&self, app: &mut App) {
+    app.add_plugin(MaterialPlugin::<ZombieMaterial>::default());
+    app
+      .sub_app_mut(RenderApp)
+      .add_system_to_stage(RenderStage::Extract, Self::extract_health)
+      .add_system_to_stage(RenderStage::Prepare, Self::prepare_health);
+  }
+}
+
+impl ZombieRenderPlugin {
+  fn prepare_health(
+    materials: Res<RenderMaterials<ZombieMaterial>>,
+    mut health_query: Query<(&mut Health, &Handle<ZombieMaterial>, &LitState)>,
+    render_queue: Res<RenderQueue>,
+  ) {
+    for (mut health, handle, lit) in &mut health_query {
+      if !health.reset_changed() && !lit.changed {
+        continue;
+      }
+
+      let color = match lit.lit {
+        true => {
+          let c = health.color();
+          let glow = |v: f32| v + (1. - v) * LIT_GLOW;
+          Color::rgb(glow(c.r()), glow(c.g()), glow(c.b()))
+        }
+        false => health.color(),
+      };
+
+      if let Some(material) = materials.get(handle) {
+        for binding in material.bindings.iter() {
+          if let OwnedBindingResource::Buffer(cur_buffer) = binding {
+            let mut buffer = encase::UniformBuffer::new(Vec::new());
+            buffer.write(&ZombieMaterialUniformData { color }).unwrap();
+            render_queue.write_buffer(cur_buffer, 0, buffer.as_ref());
+          }
+        }
+      }
+    }
+  }
+
+  fn extract_health(
+    mut commands: Commands,
+    health_query: Extract<ExtractedZombies>,
+    unlit: Extract<RemovedComponents<Lit>>,
+  ) {
+    let unlit = unlit.iter().collect::<HashSet<_>>();
+    for (entity, health, handle, lit) in health_query.iter() {
+      commands
+        .get_or_spawn(entity)
+        .insert(*health)
+        .insert(handle.clone())
+        .insert(LitState {
+          lit: lit.is_some(),
+          changed: lit.is_some_and(|lit| lit.is_changed()) || unlit.contains(&entity),
+        });
+    }
+  }
+}
@@ -0,0 +1,311 @@
+use crate::*;
+use bevy::{
+  asset::AssetPlugin, scene::Scene, time::create_time_channels, transform::TransformSystem,
+};
+
+/// Seconds of game time a tick simulates.
+const TICK: f32 = 1. / 60.;
+/// Ticks simulated when none are asked for, a minute of game time.
+pub const TICKS: usize = 3600;
+/// How close a zombie has to get to count as having reached the player. Paths end in the player's
+/// cell, where zombies mill about rather than close in, so within a cell is close enough.
+const REACH: f32 = CELL_SIZE;
+/// Anything below this has fallen through the floor.
+const FLOOR: f32 = -1.;
+/// The scripted player makes a noise this often, so every zombie in the building comes for them.
+const NOISE_TICKS: usize = 60;
+/// How close the scripted player has to get to a waypoint before heading for the next one.
+const WAYPOINT_REACH: f32 = 2.;
+const WALK_FORCE: f32 = 25000.;
+
+/// Generates a building without a window or renderer, fills it with zombies and steps the game
+/// for a number of ticks with a scripted player, for checking the game works on a machine without
+/// a GPU. Game time steps a fixed tick an update and the AI is seeded, so the same seed and ticks
+/// always give the same report. Run with `--headless [ticks] [seed]`.
+pub struct Simulation {
+  pub seed: u64,
+  pub ticks: usize,
+  /// Where the player walks, in order, before standing still. Empty to stand still from the start.
+  pub route: Vec<Vec3>,
+}
+
+/// What a simulation found, a line per check.
+pub struct Report {
+  pub seed: u64,
+  pub ticks: usize,
+  pub checks: Vec<(&'static str, Result<String, String>)>,
+}
+
+/// Moves the player along `Simulation::route` and makes noise now and then.
+struct Script {
+  route: Vec<Vec3>,
+  next: usize,
+  tick: usize,
+}
+
+/// What's been seen over the simulation so far.
+#[derive(Default)]
+struct Tracking {
+  /// How close each zombie has come to the player.
+  closest: HashMap<Entity, f32>,
+  fell: HashSet<Entity>,
+}
+
+impl Default for Simulation {
+  fn default() -> Self {
+    Self {
+      seed: rand::random(),
+      ticks: TICKS,
+      route: vec![],
+    }
+  }
+}
+
+impl Simulation {
+  /// `--headless [ticks] [seed]`, or `None` when not running headless.
+  pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+    if args.next().as_deref() != Some("--headless") {
+      return None;
+    }
+
+    let mut sim = Self::default();
+    if let Some(ticks) = args.next().and_then(|ticks| ticks.parse().ok()) {
+      sim.ticks = ticks;
+    }
+    if let Some(seed) = args.next().and_then(|seed| seed.parse().ok()) {
+      sim.seed = seed;
+    }
+    Some(sim)
+  }
+
+  pub fn run(&self) -> Report {
+    // zombies of anything simulated before
+    Zombie::reset_count();
+
+    let mut app = App::new();
+    app
+      .add_plugins(MinimalPlugins)
+      .add_plugin(TransformPlugin)
+      .add_plugin(HierarchyPlugin)
+      .add_plugin(AssetPlugin)
+      .add_asset::<Mesh>()
+      .add_asset::<Image>()
+      .add_asset::<Scene>()
+      .add_asset::<StandardMaterial>()
+      .add_asset::<ZombieMaterial>()
+      .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+      .insert_resource(RapierConfiguration {
+        timestep_mode: TimestepMode::Fixed {
+          dt: TICK,
+          substeps: 1,
+        },
+        ..default()
+      })
+      .insert_resource(Zones::default())
+      .insert_resource(AssetCache::default())
+      .insert_resource(TimeOfDay::default())
+      .insert_resource(Weather::new(self.seed))
+      .insert_resource(Noise::default())
+      .insert_resource(AiRng::new(self.seed))
+      .insert_resource(PathQueue::default())
+      .insert_resource(WorldSeed(self.seed))
+      .insert_resource(Script {
+        route: self.route.clone(),
+        next: 0,
+        tick: 0,
+      })
+      .insert_resource(Tracking::default())
+      .add_startup_system(Self::setup)
+      // one after the other, so entities are spawned in the same order every run
+      .add_startup_system(Player::setup.after(Self::setup))
+      .add_system(Script::update)
+      // it changes what zombies see and hear, and is as seeded as they are
+      .add_system(Weather::update.before(Zombie::update_normal))
+      .add_system(Zombie::update_normal.before(Zombie::update_aggressive))
+      .add_system(Zombie::update_aggressive.after(Script::update))
+      .add_system(Zombie::find_paths.after(Zombie::update_aggressive))
+      .add_system_to_stage(
+        CoreStage::PostUpdate,
+        Zones::update.after(TransformSystem::TransformPropagate),
+      )
+      .add_system_to_stage(CoreStage::Last, Tracking::update);
+
+    // game time moves on a tick an update however fast the machine runs them
+    let (time_tx, time_rx) = create_time_channels();
+    app.insert_resource(time_rx);
+    let startup = app.world.resource::<Time>().startup();
+    for tick in 1..=self.ticks {
+      let _ = time_tx
+        .0
+        .send(startup + Duration::from_secs_f64(tick as f64 * TICK as f64));
+      app.update();
+    }
+
+    app
+      .world
+      .resource::<Tracking>()
+      .report(self.seed, self.ticks)
+  }
+
+  fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cache: ResMut<AssetCache>,
+    mut zombie_materials: ResMut<Assets<ZombieMaterial>>,
+    ass: Res<AssetServer>,
+    seed: Res<WorldSeed>,
+    mut zones: ResMut<Zones>,
+  ) {
+    let (_, building) = Building::fabricate(
+      &mut commands,
+      &mut meshes,
+      &mut materials,
+      &mut cache,
+      &mut zombie_materials,
+      &ass,
+      Transform::default(),
+      seed.0,
+      &[],
+      &[],
+      true,
+      false,
+    );
+    zones.insert_building(building);
+  }
+}
+
+impl Script {
+  fn update(
+    mut this: ResMut<Self>,
+    mut noise: ResMut<Noise>,
+    time: Res<Time>,
+    mut query: Query<(&Transform, &mut ExternalForce), With<Player>>,
+  ) {
+    this.tick += 1;
+    let (t, mut force) = match query.iter_mut().next() {
+      Some(player) => player,
+      None => return,
+    };
+
+    if this.tick % NOISE_TICKS == 1 {
+      noise.make(t.translation, &time);
+    }
+
+    force.force = Vec3::ZERO;
+    if let Some(waypoint) = this.route.get(this.next).copied() {
+      let to = Vec3::new(
+        waypoint.x - t.translation.x,
+        0.,
+        waypoint.z - t.translation.z,
+      );
+      match to.length() <= WAYPOINT_REACH {
+        true => this.next += 1,
+        false => force.force = to.normalize() * WALK_FORCE,
+      }
+    }
+  }
+}
+
+impl Tracking {
+  fn update(
+    mut this: ResMut<Self>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    zombie_query: Query<(Entity, &Transform), With<Zombie>>,
+  ) {
+    let (entity, player) = match player_query.iter().next() {
+      Some((entity, t)) => (entity, t.translation),
+      None => return,
+    };
+    if player.y < FLOOR {
+      this.fell.insert(entity);
+    }
+
+    for (entity, t) in &zombie_query {
+      let dist = t.translation.distance(player);
+      let closest = this.closest.entry(entity).or_insert(dist);
+      *closest = closest.min(dist);
+      if t.translation.y < FLOOR {
+        this.fell.insert(entity);
+      }
+    }
+  }
+
+  fn report(&self, seed: u64, ticks: usize) -> Report {
+    let zombies = self.closest.len();
+    let reached = self.closest.values().filter(|dist| **dist <= REACH).count();
+    // the zombie that got the least far
+    let furthest = self.closest.values().fold(0_f32, |a, b| a.max(*b));
+    let mut checks = vec![];
+
+    checks.push((
+      "zombies spawned",
+      match zombies {
+        0 => Err("none".to_string()),
+        _ => Ok(zombies.to_string()),
+      },
+    ));
+    checks.push((
+      "every zombie reached the player",
+      match reached == zombies {
+        true => Ok(format!("{}/{}", reached, zombies)),
+        false => Err(format!(
+          "{}/{}, one only got within {:.1}",
+          reached, zombies, furthest
+        )),
+      },
+    ));
+    checks.push((
+      "no entity fell through the floor",
+      match self.fell.len() {
+        0 => Ok("none".to_string()),
+        fell => Err(format!("{} fell", fell)),
+      },
+    ));
+
+    Report {
+      seed,
+      ticks,
+      checks,
+    }
+  }
+}
+
+impl Report {
+  pub fn passed(&self) -> bool {
+    self.checks.iter().all(|(_, result)| result.is_ok())
+  }
+}
+
+impl std::fmt::Display for Report {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Seed {}, {} ticks", self.seed, self.ticks)?;
+    for (check, result) in &self.checks {
+      match result {
+        Ok(detail) => writeln!(f, "PASS {} ({})", check, detail)?,
+        Err(detail) => writeln!(f, "FAIL {} ({})", check, detail)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Simulations share the zombie count and the zones channel, so they take turns in one test.
+  #[test]
+  fn simulation_passes_the_same_every_run() {
+    for seed in [1, 2] {
+      let sim = Simulation {
+        seed,
+        ticks: TICKS,
+        route: vec![],
+      };
+      let report = sim.run();
+      assert!(report.passed(), "{}", report);
+      assert_eq!(sim.run().to_string(), report.to_string());
+    }
+  }
+}
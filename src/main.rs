@@ -1,15 +1,14 @@
 #![feature(let_chains)]
 
-pub use bevy::ecs::system::EntityCommands;
+pub use bevy::ecs::system::{EntityCommands, SystemState};
 pub use bevy::prelude::*;
 use bevy::{
-  diagnostic::FrameTimeDiagnosticsPlugin,
-  render::{RenderApp, RenderStage},
+  asset::diagnostic::AssetCountDiagnosticsPlugin, diagnostic::FrameTimeDiagnosticsPlugin,
+  input::InputSystem, transform::TransformSystem,
 };
 pub use bevy_rapier3d::prelude::*;
 use bevy_turborand::*;
 pub use component::*;
-use crossbeam_channel::{unbounded, Receiver, Sender};
 pub use lazy_static::lazy_static;
 pub use parking_lot::{Mutex, RwLock};
 use rand::Rng;
@@ -26,47 +25,162 @@ pub use std::{
 pub use system::*;
 
 mod component;
+mod headless;
 mod system;
 
 fn main() {
+  if let Some(sim) = headless::Simulation::from_args(std::env::args().skip(1)) {
+    let report = sim.run();
+    print!("{}", report);
+    std::process::exit(match report.passed() {
+      true => 0,
+      false => 1,
+    });
+  }
+
   let mut app = App::new();
 
   app
-    .insert_resource(CommonMaterials::default())
+    .insert_resource(AssetCache::default())
     .insert_resource(Zones::default())
     .insert_resource(road::RoadGrid::default())
+    .insert_resource(Chunks::default())
+    .insert_resource(BakeBuildings::default())
+    .insert_resource(TimeOfDay::default())
+    .insert_resource(Weather::default())
+    .insert_resource(Noise::default())
+    .insert_resource(AiRng::new(0))
+    .insert_resource(FogOfWar::default())
+    .insert_resource(MapMemory::default())
+    .insert_resource(PathQueue::default())
+    .insert_resource(DebugOverlays::default())
+    .insert_resource(
+      Console::default()
+        .with::<GameState>()
+        .with::<Player>()
+        .with::<Zombie>()
+        .with::<Building>()
+        .with::<BakeBuildings>()
+        .with::<TimeOfDay>()
+        .with::<DebugOverlays>(),
+    )
+    .insert_resource(Kills::default())
+    .insert_resource(Actions::default())
+    .insert_resource(WorldSeed::default())
+    .insert_resource(PendingLoad::default())
     .add_plugin(RngPlugin::default())
     .add_plugin(FrameTimeDiagnosticsPlugin::default())
+    .add_plugin(AssetCountDiagnosticsPlugin::<Mesh>::default())
+    .add_plugin(AssetCountDiagnosticsPlugin::<StandardMaterial>::default())
     .add_plugins(DefaultPlugins)
+    // loaded through the asset server, so only once it's there
+    .init_resource::<UiFont>()
+    // after the log plugin, so problems with the config end up in the log
+    .insert_resource(Controls::load())
     .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-    .add_plugin(MaterialPlugin::<ZombieMaterial>::default())
-    // .add_plugin(RapierDebugRenderPlugin::default())
-    .add_startup_system_to_stage(
-      StartupStage::PreStartup,
-      component::building::Room::load_materials,
-    )
-    .add_startup_system(component::Player::setup)
+    .add_plugin(ZombieRenderPlugin)
+    .add_plugin(RapierDebugRenderPlugin::default())
+    // only drawn while `Overlay::Colliders` is shown
+    .insert_resource(DebugRenderContext {
+      enabled: false,
+      ..default()
+    })
+    .add_state(GameState::MainMenu)
     .add_startup_system(component::Camera::setup)
-    .add_startup_system(component::Grass::setup)
-    .add_startup_system(component::Building::spawn)
-    .add_startup_system(component::DebugText::spawn)
+    .add_startup_system(TimeOfDay::setup)
+    .add_startup_system_to_stage(StartupStage::PostStartup, Weather::setup)
+    .add_startup_system(component::DebugPanel::spawn)
+    .add_startup_system(Hud::spawn)
+    .add_startup_system(Map::spawn)
+    .add_startup_system(Console::spawn)
+    .add_startup_system(Console::load_script)
+    .add_system_to_stage(CoreStage::PreUpdate, Actions::update.after(InputSystem))
+    // despawns from `Update` only show up as removals once its commands have been applied
+    .add_system_to_stage(
+      CoreStage::PostUpdate,
+      Zones::update.after(TransformSystem::TransformPropagate),
+    )
     .add_system(component::Camera::follow_player)
-    .add_system(component::Player::update)
-    .add_system(component::Zombie::update_normal)
-    .add_system(component::Zombie::update_aggressive)
-    .add_system(component::Zombie::update_impact)
-    .add_system(component::Bullet::spawn)
-    .add_system(component::Bullet::update)
-    .add_system(Zones::update)
-    .add_system(road::RoadGrid::update)
-    .add_system(component::DebugText::update);
-
-  app
-    .sub_app_mut(RenderApp)
-    .add_system_to_stage(RenderStage::Extract, Zombie::extract_health)
-    .add_system_to_stage(RenderStage::Prepare, Zombie::prepare_health);
+    .add_system(component::DebugPanel::update)
+    .add_system(DebugOverlays::toggle_pressed)
+    .add_system(DebugOverlays::update.after(DebugOverlays::toggle_pressed))
+    .add_system(Hud::update)
+    .add_system(Console::input)
+    .add_system(Console::update_panel.after(Console::input))
+    .add_system(Console::run.exclusive_system())
+    .add_system(Save::quickload)
+    .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(Menu::spawn_main))
+    .add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(Menu::start_or_quit))
+    .add_system_set(SystemSet::on_exit(GameState::MainMenu).with_system(Menu::despawn))
+    .add_system_set(
+      SystemSet::on_enter(GameState::Loading)
+        .with_system(GameState::teardown)
+        .with_system(GameState::resume_physics)
+        .with_system(GameState::choose_seed)
+        .with_system(TimeOfDay::reset)
+        .with_system(Weather::reset.after(GameState::choose_seed))
+        .with_system(Noise::reset)
+        .with_system(AiRng::reset.after(GameState::choose_seed))
+        .with_system(MapMemory::reset)
+        .with_system(PathQueue::reset)
+        .with_system(Kills::reset)
+        .with_system(component::Player::setup.after(GameState::teardown)),
+    )
+    .add_system_set(
+      SystemSet::on_update(GameState::Loading)
+        .with_system(Save::restore)
+        .with_system(Chunks::update.after(Save::restore))
+        .with_system(GameState::finish_loading.after(Chunks::update)),
+    )
+    .add_system_set(
+      SystemSet::on_update(GameState::Playing)
+        .with_system(Menu::pause)
+        .with_system(Save::quicksave)
+        .with_system(GameState::check_game_over)
+        .with_system(component::Player::update)
+        .with_system(component::God::update)
+        .with_system(component::Flashlight::update)
+        .with_system(component::Weapon::update.before(component::Bullet::spawn))
+        .with_system(component::Flashlight::light_zombies.before(component::Zombie::update_impact))
+        .with_system(component::Zombie::update_normal.before(component::Zombie::update_aggressive))
+        .with_system(component::Zombie::update_aggressive)
+        .with_system(component::Zombie::find_paths.after(component::Zombie::update_aggressive))
+        .with_system(component::Zombie::update_impact)
+        .with_system(component::Bullet::spawn)
+        .with_system(component::Bullet::update)
+        .with_system(Chunks::update)
+        .with_system(Chunks::deploy.after(Chunks::update))
+        .with_system(TimeOfDay::update)
+        .with_system(Weather::update)
+        .with_system(Weather::update_fog.after(Weather::update))
+        .with_system(Weather::update_wetness.after(Weather::update))
+        .with_system(Weather::update_rain.after(Weather::update))
+        .with_system(building::LightSwitch::interact)
+        .with_system(building::RoomLight::update.after(building::LightSwitch::interact))
+        .with_system(FogOfWar::update)
+        .with_system(FogOfWar::hide_zombies.after(FogOfWar::update))
+        .with_system(building::Roof::update.after(FogOfWar::update))
+        .with_system(building::FogCover::update.after(FogOfWar::update))
+        .with_system(Map::update.after(FogOfWar::update)),
+    )
+    .add_system_set(
+      SystemSet::on_enter(GameState::Paused)
+        .with_system(Menu::spawn_pause)
+        .with_system(GameState::pause_physics),
+    )
+    .add_system_set(SystemSet::on_update(GameState::Paused).with_system(Menu::paused))
+    .add_system_set(
+      SystemSet::on_exit(GameState::Paused)
+        .with_system(Menu::despawn)
+        .with_system(GameState::resume_physics),
+    )
+    .add_system_set(
+      SystemSet::on_enter(GameState::GameOver)
+        .with_system(Menu::spawn_game_over)
+        .with_system(GameState::pause_physics),
+    )
+    .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(Menu::start_or_quit))
+    .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(Menu::despawn));
 
   app.run();
 }
-
-pub type CommonMaterials = HashMap<String, Handle<StandardMaterial>>;
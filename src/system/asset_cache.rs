@@ -0,0 +1,115 @@
+use crate::*;
+use bevy::{asset::diagnostic::AssetCountDiagnosticsPlugin, diagnostic::Diagnostics};
+
+/// A generated shape, by everything that goes into making it. Sizes are kept as bits so keys can
+/// be hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeshKey {
+  Box([u32; 3]),
+  Plane(u32),
+  Cube(u32),
+  UVSphere {
+    radius: u32,
+    sectors: usize,
+    stacks: usize,
+  },
+}
+
+impl MeshKey {
+  pub fn cuboid(x: f32, y: f32, z: f32) -> Self {
+    Self::Box([x.to_bits(), y.to_bits(), z.to_bits()])
+  }
+
+  pub fn plane(size: f32) -> Self {
+    Self::Plane(size.to_bits())
+  }
+
+  pub fn cube(size: f32) -> Self {
+    Self::Cube(size.to_bits())
+  }
+
+  pub fn uv_sphere(radius: f32, sectors: usize, stacks: usize) -> Self {
+    Self::UVSphere {
+      radius: radius.to_bits(),
+      sectors,
+      stacks,
+    }
+  }
+
+  pub fn mesh(self) -> Mesh {
+    match self {
+      Self::Box([x, y, z]) => Mesh::from(shape::Box::new(
+        f32::from_bits(x),
+        f32::from_bits(y),
+        f32::from_bits(z),
+      )),
+      Self::Plane(size) => Mesh::from(shape::Plane {
+        size: f32::from_bits(size),
+      }),
+      Self::Cube(size) => Mesh::from(shape::Cube {
+        size: f32::from_bits(size),
+      }),
+      Self::UVSphere {
+        radius,
+        sectors,
+        stacks,
+      } => Mesh::from(shape::UVSphere {
+        radius: f32::from_bits(radius),
+        sectors,
+        stacks,
+      }),
+    }
+  }
+}
+
+/// Handles to generated meshes and materials, so identical walls, floors, roads and zombies share
+/// one asset instead of adding their own every time they are spawned.
+#[derive(Default)]
+pub struct AssetCache {
+  meshes: HashMap<MeshKey, Handle<Mesh>>,
+  /// By a name describing the material, like `"road/line"` or `"wallpaper/2"`.
+  materials: HashMap<String, Handle<StandardMaterial>>,
+}
+
+impl AssetCache {
+  pub fn mesh(&mut self, meshes: &mut Assets<Mesh>, key: MeshKey) -> Handle<Mesh> {
+    self
+      .meshes
+      .entry(key)
+      .or_insert_with(|| meshes.add(key.mesh()))
+      .clone()
+  }
+
+  /// The material cached as `key`, made by `material` the first time it's asked for.
+  pub fn material(
+    &mut self,
+    materials: &mut Assets<StandardMaterial>,
+    key: &str,
+    material: impl FnOnce() -> StandardMaterial,
+  ) -> Handle<StandardMaterial> {
+    if let Some(handle) = self.materials.get(key) {
+      return handle.clone();
+    }
+    let handle = materials.add(material());
+    self.materials.insert(key.to_string(), handle.clone());
+    handle
+  }
+
+  pub fn cached_material(&self, key: &str) -> Option<&Handle<StandardMaterial>> {
+    self.materials.get(key)
+  }
+
+  /// How many meshes and materials are loaded, as last measured by the asset count diagnostics.
+  pub fn asset_counts(diagnostics: &Diagnostics) -> (usize, usize) {
+    let count = |id| {
+      diagnostics
+        .get(id)
+        .and_then(|d| d.value())
+        .unwrap_or_default() as usize
+    };
+    (
+      count(AssetCountDiagnosticsPlugin::<Mesh>::diagnostic_id()),
+      count(AssetCountDiagnosticsPlugin::<StandardMaterial>::diagnostic_id()),
+    )
+  }
+}
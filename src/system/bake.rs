@@ -0,0 +1,294 @@
+use crate::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+
+/// Whether buildings are baked into a few merged meshes and one collider when they're spawned,
+/// rather than an entity for every floor, wall and trim. Off by default, changed with `set bake`.
+#[derive(Default)]
+pub struct BakeBuildings(pub bool);
+
+impl ConsoleCommands for BakeBuildings {
+  fn register(console: &mut Console) {
+    console.var(
+      "bake",
+      "Whether buildings in chunks loaded from now on are baked",
+      |world| world.resource::<Self>().0.to_string(),
+      |world, value| {
+        world.resource_mut::<Self>().0 = value.parse().map_err(|_| "Not true or false")?;
+        Ok(())
+      },
+    );
+  }
+}
+
+/// One static piece of generated geometry, relative to whatever it's being built for.
+pub enum Part {
+  Mesh {
+    key: MeshKey,
+    material: Handle<StandardMaterial>,
+    transform: Transform,
+  },
+  Collider {
+    collider: Collider,
+    transform: Transform,
+  },
+}
+
+impl Part {
+  /// The same part, relative to `parent`'s parent instead.
+  pub fn transformed(self, parent: &Transform) -> Self {
+    match self {
+      Self::Mesh {
+        key,
+        material,
+        transform,
+      } => Self::Mesh {
+        key,
+        material,
+        transform: parent.mul_transform(transform),
+      },
+      Self::Collider {
+        collider,
+        transform,
+      } => Self::Collider {
+        collider,
+        transform: parent.mul_transform(transform),
+      },
+    }
+  }
+
+  /// Spawns the part as an entity of its own.
+  pub fn fabricate(
+    self,
+    child_builder: &mut ChildBuilder,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    cache: &mut ResMut<AssetCache>,
+  ) -> Entity {
+    match self {
+      Self::Mesh {
+        key,
+        material,
+        transform,
+      } => child_builder
+        .spawn_bundle(PbrBundle {
+          mesh: cache.mesh(meshes, key),
+          material,
+          transform,
+          ..default()
+        })
+        .id(),
+      Self::Collider {
+        collider,
+        transform,
+      } => child_builder
+        .spawn_bundle(TransformBundle::from(transform))
+        .insert(collider)
+        .id(),
+    }
+  }
+}
+
+/// Collects static parts and merges them into one mesh per material and one compound collider.
+#[derive(Default)]
+pub struct Baker {
+  meshes: HashMap<Handle<StandardMaterial>, Vec<(MeshKey, Transform)>>,
+  colliders: Vec<(Vec3, Quat, Collider)>,
+}
+
+impl Baker {
+  pub fn add(&mut self, part: Part) {
+    match part {
+      Part::Mesh {
+        key,
+        material,
+        transform,
+      } => self
+        .meshes
+        .entry(material)
+        .or_default()
+        .push((key, transform)),
+      Part::Collider {
+        collider,
+        transform,
+      } => self
+        .colliders
+        .push((transform.translation, transform.rotation, collider)),
+    }
+  }
+
+  /// Spawns everything collected as children of the entity being built.
+  pub fn fabricate(self, child_builder: &mut ChildBuilder, meshes: &mut ResMut<Assets<Mesh>>) {
+    for (material, parts) in self.meshes {
+      child_builder.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Self::merge(&parts)),
+        material,
+        ..default()
+      });
+    }
+
+    if !self.colliders.is_empty() {
+      child_builder
+        .spawn_bundle(TransformBundle::default())
+        .insert(Collider::compound(self.colliders));
+    }
+  }
+
+  /// One mesh with the triangles of every part. Parts can't be scaled unevenly, or their normals
+  /// would come out wrong.
+  fn merge(parts: &[(MeshKey, Transform)]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+
+    for (key, transform) in parts {
+      let mesh = key.mesh();
+      let offset = positions.len() as u32;
+      let matrix = transform.compute_matrix();
+
+      if let Some(VertexAttributeValues::Float32x3(ps)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        positions.extend(
+          ps.iter()
+            .map(|p| matrix.transform_point3(Vec3::from(*p)).to_array()),
+        );
+      }
+      if let Some(VertexAttributeValues::Float32x3(ns)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        normals.extend(
+          ns.iter()
+            .map(|n| (transform.rotation * Vec3::from(*n)).to_array()),
+        );
+      }
+      if let Some(VertexAttributeValues::Float32x2(us)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        uvs.extend(us);
+      }
+      match mesh.indices() {
+        Some(Indices::U32(is)) => indices.extend(is.iter().map(|i| i + offset)),
+        Some(Indices::U16(is)) => indices.extend(is.iter().map(|i| *i as u32 + offset)),
+        None => indices.extend(offset..positions.len() as u32),
+      }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bevy::{asset::AssetPlugin, scene::Scene};
+  use itertools::Itertools;
+
+  const SEEDS: u64 = 20;
+
+  /// Spawns a building the way a chunk would, without a window or renderer.
+  fn spawn_building(seed: u64, bake: bool) -> World {
+    let mut app = App::new();
+    app
+      .add_plugins(MinimalPlugins)
+      .add_plugin(AssetPlugin)
+      .add_asset::<Mesh>()
+      .add_asset::<Image>()
+      .add_asset::<Scene>()
+      .add_asset::<StandardMaterial>()
+      .add_asset::<ZombieMaterial>()
+      .insert_resource(AssetCache::default());
+
+    let mut state: SystemState<(
+      Commands,
+      ResMut<Assets<Mesh>>,
+      ResMut<Assets<StandardMaterial>>,
+      ResMut<AssetCache>,
+      ResMut<Assets<ZombieMaterial>>,
+      Res<AssetServer>,
+    )> = SystemState::new(&mut app.world);
+    let (mut commands, mut meshes, mut materials, mut cache, mut zombie_materials, ass) =
+      state.get_mut(&mut app.world);
+    let origin = Transform::from_xyz(100., 0.1, -300.)
+      .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2));
+    Building::fabricate(
+      &mut commands,
+      &mut meshes,
+      &mut materials,
+      &mut cache,
+      &mut zombie_materials,
+      &ass,
+      origin,
+      seed,
+      &[],
+      &[],
+      false,
+      bake,
+    );
+    state.apply(&mut app.world);
+    app.world
+  }
+
+  /// Every collider in the world, and every shape of compound ones, as its shape and where it ends
+  /// up in the world, rounded so the same collider reached two ways compares equal.
+  fn colliders(world: &mut World) -> Vec<String> {
+    let mut query = world.query::<(Entity, &Collider)>();
+    let mut transforms = world.query::<(&Transform, Option<&Parent>)>();
+    let world = &*world;
+    let mut global = |entity: Entity| {
+      let mut t = Transform::identity();
+      let mut next = Some(entity);
+      while let Some(entity) = next {
+        let (local, parent) = transforms.get(world, entity).unwrap();
+        t = local.mul_transform(t);
+        next = parent.map(|p| p.get());
+      }
+      t
+    };
+
+    let mut colliders = vec![];
+    for (entity, collider) in query.iter(world) {
+      let t = global(entity);
+      match collider.as_compound() {
+        Some(compound) => {
+          for (pos, rot, shape) in compound.shapes() {
+            let part = Transform::from_translation(pos).with_rotation(rot);
+            colliders.push(describe(&t.mul_transform(part), shape));
+          }
+        }
+        None => colliders.push(describe(&t, collider.as_typed_shape())),
+      }
+    }
+    colliders.sort();
+    colliders
+  }
+
+  fn describe(t: &Transform, shape: ColliderView) -> String {
+    let shape = match shape {
+      ColliderView::Cuboid(cuboid) => format!("cuboid {:.2}", cuboid.half_extents() * t.scale),
+      ColliderView::Ball(ball) => format!("ball {:.2}", ball.radius() * t.scale.x),
+      ColliderView::Cylinder(cylinder) => format!(
+        "cylinder {:.2} {:.2}",
+        cylinder.half_height() * t.scale.y,
+        cylinder.radius() * t.scale.x
+      ),
+      _ => "other".to_string(),
+    };
+    // the corners land in the same places however the box is turned
+    let corners = [-1., 1.]
+      .into_iter()
+      .flat_map(|x| [-1., 1.].map(|z| t.mul_vec3(Vec3::new(x, 0., z))))
+      .map(|p| format!("{:.1},{:.1},{:.1}", p.x, p.y, p.z) + " ")
+      .sorted()
+      .collect::<String>();
+    format!("{} at {}", shape, corners).replace("-0.0", "0.0")
+  }
+
+  #[test]
+  fn baked_buildings_collide_the_same() {
+    for seed in 0..SEEDS {
+      let unbaked = colliders(&mut spawn_building(seed, false));
+      let baked = colliders(&mut spawn_building(seed, true));
+      assert!(!unbaked.is_empty());
+      assert_eq!(baked, unbaked, "seed {}", seed);
+    }
+  }
+}
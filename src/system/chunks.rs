@@ -0,0 +1,449 @@
+use crate::{
+  building::{PowerState, WallChange},
+  road::RoadGrid,
+  *,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Chunks up to this many road grid cells away from the player's are loaded.
+const LOAD_RADIUS: i16 = 1;
+/// Chunks further away than this are unloaded. Bigger than `LOAD_RADIUS` so walking back and forth
+/// over a chunk border doesn't keep reloading the same chunks.
+const UNLOAD_RADIUS: i16 = 2;
+/// Chance of a lot having a building on it.
+const LOT_CHANCE: f64 = 0.7;
+/// Where the player starts, in a building of its own.
+const HOME: Vec3 = Vec3::new(0., 0.1, 0.);
+
+pub type ZombieQuery<'w, 's> = Query<
+  'w,
+  's,
+  (
+    Entity,
+    &'static Transform,
+    &'static Health,
+    Option<&'static Aggressive>,
+    &'static Zombie,
+  ),
+  With<Zombie>,
+>;
+
+/// What changed in a chunk since it was generated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkState {
+  /// Zombies left in the chunk. They replace the ones the chunk would otherwise generate.
+  pub zombies: Vec<ZombieSave>,
+  /// Wall changes of each building, in the order the chunk generates its buildings.
+  pub wall_changes: Vec<Vec<WallChange>>,
+  /// Power and light switches of each building, in the same order. Missing from older saves.
+  #[serde(default)]
+  pub power: Vec<PowerState>,
+  /// Rooms the player has seen into in each building, in the same order.
+  #[serde(default)]
+  pub seen_rooms: Vec<Vec<usize>>,
+}
+
+struct LoadedChunk {
+  entities: Vec<Entity>,
+  buildings: Vec<Arc<Building>>,
+  /// Zombies of the chunk there was no room for under the zombie limit. They stay part of the
+  /// chunk, and are spawned as soon as there is room.
+  undeployed: Vec<ZombieSave>,
+}
+
+/// Streams the world in around the player one road grid cell at a time: roads, grass, buildings
+/// and zombies. Unloaded chunks keep their state, so they come back the way they were left.
+#[derive(Default)]
+pub struct Chunks {
+  center: Option<Coord>,
+  loaded: HashMap<Coord, LoadedChunk>,
+  /// State of the chunks that aren't loaded, either unloaded or restored from a save.
+  pub saved: HashMap<Coord, ChunkState>,
+}
+
+impl Chunks {
+  #[inline]
+  fn distance(a: Coord, b: Coord) -> i16 {
+    (a.x - b.x).abs().max((a.z - b.z).abs())
+  }
+
+  /// Only does anything when the player moves into another chunk.
+  pub fn update(
+    mut chunks: ResMut<Self>,
+    seed: Res<WorldSeed>,
+    mut road_grid: ResMut<RoadGrid>,
+    mut zones: ResMut<Zones>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cache: ResMut<AssetCache>,
+    mut zombie_materials: ResMut<Assets<ZombieMaterial>>,
+    ass: Res<AssetServer>,
+    bake: Res<BakeBuildings>,
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    player_query: Query<&Transform, With<Player>>,
+    zombies: ZombieQuery,
+  ) {
+    let now = time.seconds_since_startup();
+    let center = match player_query.iter().next() {
+      Some(t) => RoadGrid::coord(&t.translation),
+      None => return,
+    };
+    if chunks.center == Some(center) {
+      return;
+    }
+    chunks.center = Some(center);
+
+    let far = chunks
+      .loaded
+      .keys()
+      .filter(|coord| Self::distance(**coord, center) > UNLOAD_RADIUS)
+      .copied()
+      .collect::<Vec<_>>();
+    chunks.unload(
+      &far,
+      now,
+      &mut commands,
+      &mut road_grid,
+      &mut zones,
+      &zombies,
+    );
+
+    for z in -LOAD_RADIUS..=LOAD_RADIUS {
+      for x in -LOAD_RADIUS..=LOAD_RADIUS {
+        let coord = Coord {
+          x: center.x + x,
+          z: center.z + z,
+        };
+        if chunks.loaded.contains_key(&coord) {
+          continue;
+        }
+
+        chunks.load(
+          coord,
+          seed.0,
+          now,
+          time_of_day.zombie_spawn_rate(),
+          &mut commands,
+          &mut road_grid,
+          &mut zones,
+          &mut meshes,
+          &mut materials,
+          &mut cache,
+          &mut zombie_materials,
+          &ass,
+          bake.0,
+        );
+      }
+    }
+  }
+
+  /// Spawns the undeployed zombies of loaded chunks once the zombie count has dropped enough to
+  /// make room for them.
+  pub fn deploy(
+    mut chunks: ResMut<Self>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut cache: ResMut<AssetCache>,
+    mut zombie_materials: ResMut<Assets<ZombieMaterial>>,
+    time: Res<Time>,
+  ) {
+    if !Zombie::has_room() {
+      return;
+    }
+    let now = time.seconds_since_startup();
+    let mut coords = chunks.loaded.keys().copied().collect::<Vec<_>>();
+    coords.sort();
+    for coord in coords {
+      let undeployed = &mut chunks.loaded.get_mut(&coord).unwrap().undeployed;
+      while let Some(zombie) = undeployed.pop() {
+        if zombie
+          .spawn(
+            now,
+            &mut commands,
+            &mut meshes,
+            &mut cache,
+            &mut zombie_materials,
+          )
+          .is_none()
+        {
+          undeployed.push(zombie);
+          return;
+        }
+      }
+    }
+  }
+
+  /// The origin and seed of every building in a chunk, in the order they are generated.
+  pub fn buildings(seed: u64, coord: Coord) -> Vec<(Transform, u64)> {
+    let mut buildings = vec![];
+    if coord == RoadGrid::coord(&HOME) {
+      buildings.push((Transform::from_translation(HOME), seed));
+    }
+
+    let mut rng = RoadGrid::cell_rng(seed, coord, Salt::Lot);
+    for lot in RoadGrid::lots(seed, coord) {
+      if rng.gen_bool(LOT_CHANCE) {
+        buildings.push((lot, rng.gen()));
+      }
+    }
+    buildings
+  }
+
+  /// The origin of every building in a chunk and the ones next to it, so neighbouring yards don't
+  /// both fence the same side.
+  pub fn lots_around(seed: u64, coord: Coord) -> Vec<Transform> {
+    (-1..=1)
+      .flat_map(|z| (-1..=1).map(move |x| Coord { x, z }))
+      .flat_map(|d| {
+        Self::buildings(
+          seed,
+          Coord {
+            x: coord.x + d.x,
+            z: coord.z + d.z,
+          },
+        )
+      })
+      .map(|(origin, _)| origin)
+      .collect()
+  }
+
+  // called from `update` with the resources it holds, grouping them would only be for this call
+  #[allow(clippy::too_many_arguments)]
+  fn load(
+    &mut self,
+    coord: Coord,
+    seed: u64,
+    now: f64,
+    spawn_rate: f32,
+    commands: &mut Commands,
+    road_grid: &mut RoadGrid,
+    zones: &mut Zones,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    zombie_materials: &mut ResMut<Assets<ZombieMaterial>>,
+    ass: &Res<AssetServer>,
+    bake: bool,
+  ) {
+    let state = self.saved.remove(&coord);
+
+    road_grid.generate(coord, seed, commands, meshes, materials, cache, ass);
+    let mut entities = vec![Grass::fabricate(
+      RoadGrid::center(coord),
+      commands,
+      meshes,
+      materials,
+      cache,
+      ass,
+    )];
+
+    let lots = Self::lots_around(seed, coord);
+    let mut buildings = vec![];
+    let mut zombies = state.as_ref().map_or(vec![], |state| state.zombies.clone());
+    for (i, (origin, building_seed)) in Self::buildings(seed, coord).into_iter().enumerate() {
+      let wall_changes = state
+        .as_ref()
+        .and_then(|state| state.wall_changes.get(i))
+        .map(|changes| changes.as_slice())
+        .unwrap_or(&[]);
+
+      let (entity, building) = Building::fabricate(
+        commands,
+        meshes,
+        materials,
+        cache,
+        zombie_materials,
+        ass,
+        origin,
+        building_seed,
+        wall_changes,
+        &lots,
+        false,
+        bake,
+      );
+      // a chunk that has been visited already brings its own zombies
+      if state.is_none() {
+        zombies.extend(
+          building
+            .zombie_spawns(spawn_rate)
+            .into_iter()
+            .map(ZombieSave::fresh),
+        );
+      }
+      if let Some(power) = state.as_ref().and_then(|state| state.power.get(i)) {
+        building.restore_power(power);
+      }
+      if let Some(seen) = state.as_ref().and_then(|state| state.seen_rooms.get(i)) {
+        building.restore_seen_rooms(seen);
+      }
+      zones.insert_building(building.clone());
+      entities.push(entity);
+      buildings.push(building);
+    }
+
+    let mut undeployed = vec![];
+    for zombie in zombies {
+      if zombie
+        .spawn(now, commands, meshes, cache, zombie_materials)
+        .is_none()
+      {
+        undeployed.push(zombie);
+      }
+    }
+
+    self.loaded.insert(
+      coord,
+      LoadedChunk {
+        entities,
+        buildings,
+        undeployed,
+      },
+    );
+  }
+
+  /// Unloads `coords` together, so a stray zombie is saved with exactly one of them rather than
+  /// handed on to the next chunk while it waits to be despawned.
+  fn unload(
+    &mut self,
+    coords: &[Coord],
+    now: f64,
+    commands: &mut Commands,
+    road_grid: &mut RoadGrid,
+    zones: &mut Zones,
+    zombies: &ZombieQuery,
+  ) {
+    let states = coords
+      .iter()
+      .filter(|coord| self.loaded.contains_key(coord))
+      .map(|coord| (*coord, self.state(*coord, zombies, now)))
+      .collect::<Vec<_>>();
+    for (entity, t, ..) in zombies.iter() {
+      if self
+        .owner(&t.translation)
+        .is_some_and(|owner| coords.contains(&owner))
+      {
+        Zombie::despawn(commands, entity);
+      }
+    }
+
+    for (coord, state) in states {
+      let chunk = self.loaded.remove(&coord).unwrap();
+      for building in &chunk.buildings {
+        zones.remove_building(building);
+      }
+      for entity in chunk.entities {
+        commands.entity(entity).despawn_recursive();
+      }
+      road_grid.remove(coord, commands);
+
+      self.saved.insert(coord, state);
+    }
+  }
+
+  /// The loaded chunk a zombie at `pos` belongs to: the one it's in, or the nearest one when it has
+  /// wandered off the loaded chunks, so it's saved and despawned along with that.
+  fn owner(&self, pos: &Vec3) -> Option<Coord> {
+    let coord = RoadGrid::coord(pos);
+    if self.loaded.contains_key(&coord) {
+      return Some(coord);
+    }
+    self
+      .loaded
+      .keys()
+      .min_by_key(|loaded| (Self::distance(**loaded, coord), **loaded))
+      .copied()
+  }
+
+  fn state(&self, coord: Coord, zombies: &ZombieQuery, now: f64) -> ChunkState {
+    let chunk = &self.loaded[&coord];
+    ChunkState {
+      zombies: zombies
+        .iter()
+        .filter(|(_, t, ..)| self.owner(&t.translation) == Some(coord))
+        .map(|(_, t, health, aggressive, zombie)| {
+          ZombieSave::new(t, health, aggressive.is_some(), zombie, now)
+        })
+        .chain(chunk.undeployed.iter().cloned())
+        .collect(),
+      wall_changes: chunk
+        .buildings
+        .iter()
+        .map(|building| building.wall_changes.read().clone())
+        .collect(),
+      power: chunk
+        .buildings
+        .iter()
+        .map(|building| building.power_state())
+        .collect(),
+      seen_rooms: chunk
+        .buildings
+        .iter()
+        .map(|building| building.seen_rooms())
+        .collect(),
+    }
+  }
+
+  /// Throws a loaded chunk away along with everything that changed in it, so the next update
+  /// generates it afresh.
+  pub fn regenerate(
+    &mut self,
+    coord: Coord,
+    now: f64,
+    commands: &mut Commands,
+    road_grid: &mut RoadGrid,
+    zones: &mut Zones,
+    zombies: &ZombieQuery,
+  ) {
+    self.unload(&[coord], now, commands, road_grid, zones, zombies);
+    self.saved.remove(&coord);
+    self.center = None;
+  }
+
+  /// Buildings of every loaded chunk.
+  pub fn loaded_buildings(&self) -> impl Iterator<Item = &Arc<Building>> {
+    self.loaded.values().flat_map(|chunk| &chunk.buildings)
+  }
+
+  pub fn is_loaded(&self, coord: Coord) -> bool {
+    self.loaded.contains_key(&coord)
+  }
+
+  /// The buildings of a chunk that has been loaded before but isn't now, laid out again from the
+  /// seed with their wall changes and seen rooms. Nothing is spawned. `None` if the chunk has never
+  /// been generated.
+  pub fn remembered_buildings(&self, seed: u64, coord: Coord) -> Option<Vec<Arc<Building>>> {
+    let state = self.saved.get(&coord)?;
+    let buildings = Self::buildings(seed, coord)
+      .into_iter()
+      .enumerate()
+      .map(|(i, (origin, building_seed))| {
+        let wall_changes = state.wall_changes.get(i).map_or(&[][..], |c| c.as_slice());
+        let building = Building::generate(origin, building_seed, wall_changes);
+        if let Some(seen) = state.seen_rooms.get(i) {
+          building.restore_seen_rooms(seen);
+        }
+        building
+      })
+      .collect();
+    Some(buildings)
+  }
+
+  /// The state of every chunk that has been generated, loaded or not, at game time `now`.
+  pub fn states(&self, zombies: &ZombieQuery, now: f64) -> Vec<(Coord, ChunkState)> {
+    let loaded = self
+      .loaded
+      .keys()
+      .map(|coord| (*coord, self.state(*coord, zombies, now)));
+    let saved = self
+      .saved
+      .iter()
+      .map(|(coord, state)| (*coord, state.clone()));
+
+    let mut states = loaded.chain(saved).collect::<Vec<_>>();
+    states.sort_by_key(|(coord, _)| *coord);
+    states
+  }
+}
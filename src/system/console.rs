@@ -0,0 +1,305 @@
+use crate::*;
+use bevy::window::ReceivedCharacter;
+use itertools::Itertools;
+use std::{
+  collections::{BTreeMap, VecDeque},
+  str::FromStr,
+};
+
+/// Run once the first world is playing, one command a line. Missing is fine.
+const SCRIPT_PATH: &str = "assets/console.txt";
+/// Lines of output kept, and shown above the input.
+const LOG_LINES: usize = 12;
+const FONT_SIZE: f32 = 18.;
+
+/// Runs a command with its arguments, what it prints on success or failure.
+pub type CommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+/// Adds the console commands of a module.
+pub trait ConsoleCommands {
+  fn register(console: &mut Console);
+}
+
+struct Command {
+  usage: &'static str,
+  help: &'static str,
+  run: CommandFn,
+}
+
+/// A value `set` can read and change.
+struct Var {
+  help: &'static str,
+  get: fn(&World) -> String,
+  set: fn(&mut World, &str) -> Result<(), String>,
+}
+
+/// A drop down console for working on the game, toggled with `Action::ToggleConsole`. Commands
+/// only run while a world is playing, anything entered before waits for one.
+pub struct Console {
+  commands: BTreeMap<&'static str, Command>,
+  vars: BTreeMap<&'static str, Var>,
+  pending: VecDeque<String>,
+  log: VecDeque<String>,
+  input: String,
+  open: bool,
+  /// Set by commands that start a new world, so what's queued after them runs in it.
+  waiting: bool,
+}
+
+/// The panel the console is shown in.
+#[derive(Component)]
+pub struct ConsolePanel;
+
+impl Default for Console {
+  fn default() -> Self {
+    let mut console = Self {
+      commands: BTreeMap::new(),
+      vars: BTreeMap::new(),
+      pending: VecDeque::new(),
+      log: VecDeque::new(),
+      input: String::new(),
+      open: false,
+      waiting: false,
+    };
+    console.command("help", "help", "Lists every command", Self::help);
+    console.command(
+      "set",
+      "set [name] [value]",
+      "Shows or changes a variable",
+      Self::set,
+    );
+    console
+  }
+}
+
+/// Parses argument `i`, naming the command's usage when it's missing or malformed.
+pub fn arg<T: FromStr>(args: &[&str], i: usize, usage: &str) -> Result<T, String> {
+  args
+    .get(i)
+    .and_then(|arg| arg.parse().ok())
+    .ok_or_else(|| format!("Usage: {}", usage))
+}
+
+impl Console {
+  pub fn with<T: ConsoleCommands>(mut self) -> Self {
+    T::register(&mut self);
+    self
+  }
+
+  pub fn command(
+    &mut self,
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: CommandFn,
+  ) {
+    self.commands.insert(name, Command { usage, help, run });
+  }
+
+  pub fn var(
+    &mut self,
+    name: &'static str,
+    help: &'static str,
+    get: fn(&World) -> String,
+    set: fn(&mut World, &str) -> Result<(), String>,
+  ) {
+    self.vars.insert(name, Var { help, get, set });
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Runs `line` once a world is playing.
+  pub fn queue(&mut self, line: &str) {
+    self.pending.push_back(line.to_string());
+  }
+
+  /// Holds the rest of the queue back until the next world is playing.
+  pub fn wait_for_world(&mut self) {
+    self.waiting = true;
+  }
+
+  fn print(&mut self, line: String) {
+    println!("{}", line);
+    for line in line.lines() {
+      self.log.push_back(line.to_string());
+    }
+    while self.log.len() > LOG_LINES {
+      self.log.pop_front();
+    }
+  }
+
+  /// Queues the startup script, skipping blank lines and `#` comments.
+  pub fn load_script(mut this: ResMut<Self>) {
+    let script = match std::fs::read_to_string(SCRIPT_PATH) {
+      Ok(script) => script,
+      Err(_) => return,
+    };
+    for line in script.lines().map(str::trim) {
+      if !line.is_empty() && !line.starts_with('#') {
+        this.queue(line);
+      }
+    }
+  }
+
+  pub fn input(
+    mut this: ResMut<Self>,
+    actions: Res<Actions>,
+    keys: Res<Input<KeyCode>>,
+    mut chars: EventReader<ReceivedCharacter>,
+  ) {
+    if actions.just_pressed(Action::ToggleConsole) {
+      this.open = !this.open;
+    }
+    if !this.open {
+      for _ in chars.iter() {}
+      return;
+    }
+
+    for event in chars.iter() {
+      // the key that opened the console types too
+      if !event.char.is_control() && event.char != '`' {
+        this.input.push(event.char);
+      }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+      this.input.pop();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+      this.open = false;
+    }
+    if keys.just_pressed(KeyCode::Return) {
+      let line = std::mem::take(&mut this.input);
+      if !line.trim().is_empty() {
+        this.print(format!("> {}", line));
+        this.queue(&line);
+      }
+    }
+  }
+
+  /// Runs what's queued, while a world is playing.
+  pub fn run(world: &mut World) {
+    loop {
+      if *world.resource::<State<GameState>>().current() != GameState::Playing {
+        return;
+      }
+      let mut this = world.resource_mut::<Self>();
+      if this.waiting {
+        this.waiting = false;
+        return;
+      }
+      let line = match this.pending.pop_front() {
+        Some(line) => line,
+        None => return,
+      };
+
+      let output = match Self::execute(world, &line) {
+        Ok(output) => output,
+        Err(err) => err,
+      };
+      if !output.is_empty() {
+        world.resource_mut::<Self>().print(output);
+      }
+    }
+  }
+
+  fn execute(world: &mut World, line: &str) -> Result<String, String> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let (name, args) = match words.split_first() {
+      Some(split) => split,
+      None => return Ok(String::new()),
+    };
+    let run = world
+      .resource::<Self>()
+      .commands
+      .get(name)
+      .map(|command| command.run)
+      .ok_or_else(|| format!("Unknown command {}, try help", name))?;
+    run(world, args)
+  }
+
+  fn help(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let this = world.resource::<Self>();
+    Ok(
+      this
+        .commands
+        .values()
+        .map(|command| format!("{} - {}", command.usage, command.help))
+        .join("\n"),
+    )
+  }
+
+  fn set(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let this = world.resource::<Self>();
+    let name = match args.first() {
+      Some(name) => name,
+      None => {
+        return Ok(
+          this
+            .vars
+            .iter()
+            .map(|(name, var)| format!("{} = {} - {}", name, (var.get)(world), var.help))
+            .join("\n"),
+        )
+      }
+    };
+    let var = this
+      .vars
+      .get(name)
+      .ok_or_else(|| format!("Unknown variable {}", name))?;
+    let (get, set) = (var.get, var.set);
+
+    if let Some(value) = args.get(1) {
+      set(world, value)?;
+    }
+    Ok(format!("{} = {}", name, get(world)))
+  }
+
+  pub fn spawn(mut commands: Commands, font: Res<UiFont>) {
+    commands
+      .spawn_bundle(NodeBundle {
+        style: Style {
+          position_type: PositionType::Absolute,
+          position: UiRect {
+            top: Val::Px(0.),
+            left: Val::Px(0.),
+            ..default()
+          },
+          size: Size::new(Val::Percent(100.), Val::Auto),
+          padding: UiRect::all(Val::Px(8.)),
+          ..default()
+        },
+        color: Color::rgba(0., 0., 0., 0.8).into(),
+        visibility: Visibility { is_visible: false },
+        ..default()
+      })
+      .insert(ConsolePanel)
+      .with_children(|panel| {
+        panel.spawn_bundle(TextBundle::from_section("", font.style(FONT_SIZE)));
+      });
+  }
+
+  pub fn update_panel(
+    this: Res<Self>,
+    mut panel_query: Query<(&mut Visibility, &Children), With<ConsolePanel>>,
+    mut text_query: Query<&mut Text>,
+  ) {
+    if !this.is_changed() {
+      return;
+    }
+
+    for (mut visibility, children) in &mut panel_query {
+      visibility.is_visible = this.open;
+      for child in children {
+        if let Ok(mut text) = text_query.get_mut(*child) {
+          text.sections[0].value = this
+            .log
+            .iter()
+            .chain([&format!("> {}_", this.input)])
+            .join("\n");
+        }
+      }
+    }
+  }
+}
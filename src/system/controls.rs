@@ -0,0 +1,346 @@
+use crate::*;
+use bevy::asset::FileAssetIo;
+use bevy::window::CursorMoved;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+const CONFIG_PATH: &str = "assets/controls.ron";
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+  MoveUp,
+  MoveDown,
+  MoveLeft,
+  MoveRight,
+  Fire,
+  Reload,
+  Interact,
+  Sprint,
+  ToggleFlashlight,
+  ToggleMap,
+  ToggleDebug,
+  ToggleConsole,
+  ToggleNavOverlay,
+  TogglePathOverlay,
+  ToggleZoneOverlay,
+  ToggleBoundsOverlay,
+  ToggleColliderOverlay,
+  Pause,
+  Quit,
+  QuickSave,
+  QuickLoad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+  Key(KeyCode),
+  Mouse(MouseButton),
+  Gamepad(GamepadButtonType),
+}
+
+/// Action bindings, the defaults below with the overrides from `assets/controls.ron`.
+/// Movement and aiming on a gamepad are always the left and right stick.
+#[derive(Debug, Clone)]
+pub struct Controls {
+  pub bindings: HashMap<Action, Vec<Binding>>,
+  pub stick_deadzone: f32,
+}
+
+/// What `assets/controls.ron` holds: only the settings that differ from the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ControlsConfig {
+  bindings: HashMap<Action, Vec<Binding>>,
+  stick_deadzone: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aim {
+  /// Aim at whatever the mouse cursor is over.
+  Cursor,
+  /// Aim in a direction relative to the camera (x: right, y: forward).
+  Stick(Vec2),
+}
+
+/// The state of every action this frame, regardless of which device triggered it.
+pub struct Actions {
+  pressed: HashSet<Action>,
+  just_pressed: HashSet<Action>,
+  /// Desired movement relative to the camera (x: right, y: forward), length at most 1.
+  pub movement: Vec2,
+  pub aim: Aim,
+}
+
+impl Controls {
+  /// The defaults with whatever `assets/controls.ron` overrides. The path is resolved the way the
+  /// asset server resolves it, so the game finds it from any working directory.
+  pub fn load() -> Self {
+    let path = FileAssetIo::get_base_path().join(CONFIG_PATH);
+    let config = match std::fs::read_to_string(&path) {
+      Ok(config) => config,
+      Err(err) => {
+        warn!(
+          "Could not read {}: {}. Using default controls.",
+          path.display(),
+          err
+        );
+        return Self::default();
+      }
+    };
+
+    match ron::from_str::<ControlsConfig>(&config) {
+      Ok(config) => Self::default().with(config),
+      Err(err) => {
+        error!(
+          "Could not parse {}: {}. Using default controls.",
+          path.display(),
+          err
+        );
+        Self::default()
+      }
+    }
+  }
+
+  /// Overlays a config onto these controls, replacing the bindings of every action it lists.
+  fn with(mut self, config: ControlsConfig) -> Self {
+    self.bindings.extend(config.bindings);
+    if let Some(stick_deadzone) = config.stick_deadzone {
+      self.stick_deadzone = stick_deadzone;
+    }
+    self
+  }
+
+  /// Human readable list of what an action is bound to, for menus and prompts.
+  pub fn describe(&self, action: Action) -> String {
+    self
+      .bindings
+      .get(&action)
+      .map(|bindings| bindings.iter().map(|b| b.to_string()).join(" / "))
+      .unwrap_or_else(|| "Unbound".into())
+  }
+}
+
+impl Default for Controls {
+  fn default() -> Self {
+    use Binding::*;
+
+    Self {
+      bindings: HashMap::from([
+        (Action::MoveUp, vec![Key(KeyCode::W), Key(KeyCode::Up)]),
+        (Action::MoveDown, vec![Key(KeyCode::S), Key(KeyCode::Down)]),
+        (Action::MoveLeft, vec![Key(KeyCode::A), Key(KeyCode::Left)]),
+        (
+          Action::MoveRight,
+          vec![Key(KeyCode::D), Key(KeyCode::Right)],
+        ),
+        (
+          Action::Fire,
+          vec![
+            Mouse(MouseButton::Left),
+            Key(KeyCode::Space),
+            Gamepad(GamepadButtonType::RightTrigger2),
+          ],
+        ),
+        (
+          Action::Reload,
+          vec![Key(KeyCode::R), Gamepad(GamepadButtonType::West)],
+        ),
+        (
+          Action::Interact,
+          vec![Key(KeyCode::E), Gamepad(GamepadButtonType::South)],
+        ),
+        (
+          Action::Sprint,
+          vec![
+            Key(KeyCode::LShift),
+            Gamepad(GamepadButtonType::LeftTrigger2),
+          ],
+        ),
+        (
+          Action::ToggleFlashlight,
+          vec![Key(KeyCode::F), Gamepad(GamepadButtonType::North)],
+        ),
+        (
+          Action::ToggleMap,
+          vec![Key(KeyCode::M), Gamepad(GamepadButtonType::DPadUp)],
+        ),
+        (
+          Action::Pause,
+          vec![Key(KeyCode::Escape), Gamepad(GamepadButtonType::Start)],
+        ),
+        (
+          Action::Quit,
+          vec![Key(KeyCode::Q), Gamepad(GamepadButtonType::Select)],
+        ),
+        (Action::ToggleDebug, vec![Key(KeyCode::F3)]),
+        (Action::ToggleConsole, vec![Key(KeyCode::Grave)]),
+        (Action::ToggleNavOverlay, vec![Key(KeyCode::F4)]),
+        (Action::TogglePathOverlay, vec![Key(KeyCode::F6)]),
+        (Action::ToggleZoneOverlay, vec![Key(KeyCode::F7)]),
+        (Action::ToggleBoundsOverlay, vec![Key(KeyCode::F8)]),
+        (Action::ToggleColliderOverlay, vec![Key(KeyCode::F10)]),
+        (Action::QuickSave, vec![Key(KeyCode::F5)]),
+        (Action::QuickLoad, vec![Key(KeyCode::F9)]),
+      ]),
+      stick_deadzone: 0.2,
+    }
+  }
+}
+
+impl Actions {
+  #[inline]
+  pub fn pressed(&self, action: Action) -> bool {
+    self.pressed.contains(&action)
+  }
+
+  #[inline]
+  pub fn just_pressed(&self, action: Action) -> bool {
+    self.just_pressed.contains(&action)
+  }
+
+  /// Like `just_pressed`, but no other system will see the press this frame.
+  /// Used when a press causes a state change, so the new state doesn't react to it too.
+  pub fn consume(&mut self, action: Action) -> bool {
+    self.just_pressed.remove(&action)
+  }
+
+  pub fn update(
+    mut actions: ResMut<Self>,
+    controls: Res<Controls>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    console: Res<Console>,
+    mut cursor_evr: EventReader<CursorMoved>,
+  ) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+
+    for (action, bindings) in &controls.bindings {
+      for binding in bindings {
+        let (pressed, just_pressed) = match *binding {
+          Binding::Key(key) => (keys.pressed(key), keys.just_pressed(key)),
+          Binding::Mouse(button) => (mouse.pressed(button), mouse.just_pressed(button)),
+          Binding::Gamepad(button_type) => gamepads.iter().fold((false, false), |acc, gamepad| {
+            let button = GamepadButton::new(*gamepad, button_type);
+            (
+              acc.0 || buttons.pressed(button),
+              acc.1 || buttons.just_pressed(button),
+            )
+          }),
+        };
+
+        if pressed {
+          actions.pressed.insert(*action);
+        }
+        if just_pressed {
+          actions.just_pressed.insert(*action);
+        }
+      }
+    }
+
+    let axis = |pos, neg| (actions.pressed(pos) as i8 - actions.pressed(neg) as i8) as f32;
+    let mut movement = Vec2::new(
+      axis(Action::MoveRight, Action::MoveLeft),
+      axis(Action::MoveUp, Action::MoveDown),
+    );
+
+    let stick = |gamepad: &Gamepad, x, y| {
+      let v = Vec2::new(
+        axes.get(GamepadAxis::new(*gamepad, x)).unwrap_or(0.),
+        axes.get(GamepadAxis::new(*gamepad, y)).unwrap_or(0.),
+      );
+      if v.length() < controls.stick_deadzone {
+        Vec2::ZERO
+      } else {
+        v
+      }
+    };
+
+    for gamepad in gamepads.iter() {
+      movement += stick(
+        gamepad,
+        GamepadAxisType::LeftStickX,
+        GamepadAxisType::LeftStickY,
+      );
+
+      let aim = stick(
+        gamepad,
+        GamepadAxisType::RightStickX,
+        GamepadAxisType::RightStickY,
+      );
+      if aim != Vec2::ZERO {
+        actions.aim = Aim::Stick(aim.normalize());
+      }
+    }
+
+    // moving the mouse hands aiming back to the cursor
+    if cursor_evr.iter().last().is_some() {
+      actions.aim = Aim::Cursor;
+    }
+
+    actions.movement = movement.clamp_length_max(1.);
+
+    // typing into the console shouldn't play the game
+    if console.is_open() {
+      actions
+        .pressed
+        .retain(|action| *action == Action::ToggleConsole);
+      actions
+        .just_pressed
+        .retain(|action| *action == Action::ToggleConsole);
+      actions.movement = Vec2::ZERO;
+    }
+  }
+}
+
+impl Default for Actions {
+  fn default() -> Self {
+    Self {
+      pressed: HashSet::new(),
+      just_pressed: HashSet::new(),
+      movement: Vec2::ZERO,
+      aim: Aim::Cursor,
+    }
+  }
+}
+
+impl std::fmt::Display for Binding {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Key(key) => write!(f, "{:?}", key),
+      Self::Mouse(button) => write!(f, "Mouse {:?}", button),
+      Self::Gamepad(button) => write!(f, "Pad {:?}", button),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn configs_override_only_what_they_list() {
+    let config =
+      ron::from_str::<ControlsConfig>("(bindings: { Sprint: [Key(LControl)] })").unwrap();
+    let controls = Controls::default().with(config);
+
+    assert_eq!(
+      controls.bindings[&Action::Sprint],
+      vec![Binding::Key(KeyCode::LControl)]
+    );
+    assert_eq!(
+      controls.bindings[&Action::Fire],
+      Controls::default().bindings[&Action::Fire]
+    );
+    assert_eq!(controls.stick_deadzone, Controls::default().stick_deadzone);
+  }
+
+  #[test]
+  fn shipped_config_parses() {
+    let config = std::fs::read_to_string(FileAssetIo::get_base_path().join(CONFIG_PATH)).unwrap();
+    ron::from_str::<ControlsConfig>(&config).unwrap();
+  }
+}
@@ -0,0 +1,88 @@
+use crate::{building::cell::WALL_NAV, *};
+
+/// What the player can see of the buildings: the room they're in, and the rooms next to it they
+/// can see through a door.
+#[derive(Default)]
+pub struct FogOfWar {
+  /// The building the player is in.
+  pub building: Option<Arc<Building>>,
+  /// Rooms in view, by id.
+  pub in_view: HashSet<usize>,
+}
+
+impl FogOfWar {
+  pub fn update(
+    mut this: ResMut<Self>,
+    zones: Res<Zones>,
+    rapier_context: Res<RapierContext>,
+    player_query: Query<&Transform, With<Player>>,
+  ) {
+    this.in_view.clear();
+    this.building = None;
+
+    let eye = match player_query.iter().next() {
+      Some(t) => t.translation,
+      None => return,
+    };
+    let cell = match zones.cell_at(&eye) {
+      Some(cell) => cell.clone(),
+      None => return,
+    };
+    let building = cell.building.clone();
+    let room = &cell.room;
+    this.in_view.insert(room.id);
+
+    let connected_to = room.connected_to.read();
+    for coord in room.cells.read().iter() {
+      let cell = &building.cells[coord];
+      for (i, adj_coord) in coord.adj().iter().enumerate() {
+        let adj = match building.cells.get(adj_coord) {
+          Some(adj) if connected_to.contains(&adj.room.id) => adj,
+          _ => continue,
+        };
+        if this.in_view.contains(&adj.room.id) || !cell.has_door(&building, i) {
+          continue;
+        }
+
+        // only walls and other fixed things block the view, at the player's height
+        let door = cell.pos + building.origin.rotation * WALL_NAV[i];
+        let to_door = Vec3::new(door.x, eye.y, door.z) - eye;
+        let blocked = rapier_context
+          .cast_ray(
+            eye,
+            to_door.normalize_or_zero(),
+            to_door.length(),
+            true,
+            QueryFilter::only_fixed(),
+          )
+          .is_some();
+        if !blocked {
+          this.in_view.insert(adj.room.id);
+        }
+      }
+    }
+
+    for id in &this.in_view {
+      if let Some(room) = building.room(*id) {
+        room.seen.store(true, Ordering::Relaxed);
+      }
+    }
+    this.building = Some(building);
+  }
+
+  /// Hides zombies inside buildings unless they're in a room in view.
+  pub fn hide_zombies(
+    this: Res<Self>,
+    zones: Res<Zones>,
+    mut query: Query<(&Transform, &mut Visibility), With<Zombie>>,
+  ) {
+    for (t, mut visibility) in &mut query {
+      let visible = zones
+        .cell_at(&t.translation)
+        .is_none_or(|cell| this.in_view.contains(&cell.room.id));
+      if visibility.is_visible != visible {
+        visibility.is_visible = visible;
+      }
+    }
+  }
+}
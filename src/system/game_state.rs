@@ -0,0 +1,106 @@
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+  MainMenu,
+  /// Tears down any previous world and generates a new one.
+  Loading,
+  Playing,
+  /// Pushed on top of `Playing`, so the world is left as it was.
+  Paused,
+  GameOver,
+}
+
+/// Top level entities the world generation spawned, leaving out the camera, sun and UI.
+type Spawned<'w, 's> = Query<
+  'w,
+  's,
+  Entity,
+  (
+    With<Transform>,
+    Without<Parent>,
+    Without<Node>,
+    Without<component::Camera>,
+    Without<Sun>,
+  ),
+>;
+
+/// Seed the world is generated from.
+pub struct WorldSeed(pub u64);
+
+impl Default for WorldSeed {
+  fn default() -> Self {
+    Self(rand::random())
+  }
+}
+
+impl GameState {
+  /// Despawns everything the world generation spawned, leaving the camera and UI alone.
+  pub fn teardown(
+    mut commands: Commands,
+    mut zones: ResMut<Zones>,
+    mut road_grid: ResMut<road::RoadGrid>,
+    mut chunks: ResMut<Chunks>,
+    query: Spawned,
+  ) {
+    for e in &query {
+      commands.entity(e).despawn_recursive();
+    }
+
+    zones.clear();
+    *road_grid = road::RoadGrid::default();
+    *chunks = Chunks::default();
+    Zombie::reset_count();
+  }
+
+  /// A save being loaded decides the seed, otherwise keep whatever was chosen.
+  pub fn choose_seed(mut seed: ResMut<WorldSeed>, load: Res<PendingLoad>) {
+    if let Some(save) = &load.0 {
+      seed.0 = save.seed;
+    }
+  }
+
+  /// Waits a frame for the spawned world to show up before playing, otherwise the game over
+  /// check would see no player.
+  pub fn finish_loading(mut state: ResMut<State<Self>>, player_query: Query<(), With<Player>>) {
+    if !player_query.is_empty() {
+      let _ = state.set(Self::Playing);
+    }
+  }
+
+  pub fn check_game_over(mut state: ResMut<State<Self>>, player_query: Query<(), With<Player>>) {
+    if player_query.is_empty() {
+      let _ = state.set(Self::GameOver);
+    }
+  }
+
+  pub fn pause_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+  }
+
+  pub fn resume_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+  }
+}
+
+impl ConsoleCommands for GameState {
+  fn register(console: &mut Console) {
+    console.command(
+      "seed",
+      "seed n",
+      "Starts a new world from a seed",
+      Self::seed,
+    );
+  }
+}
+
+impl GameState {
+  fn seed(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let seed = console::arg(args, 0, "seed n")?;
+    world.resource_mut::<WorldSeed>().0 = seed;
+    world.resource_mut::<PendingLoad>().0 = None;
+    let _ = world.resource_mut::<State<Self>>().set(Self::Loading);
+    world.resource_mut::<Console>().wait_for_world();
+    Ok(format!("Generating world {}", seed))
+  }
+}
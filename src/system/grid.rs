@@ -0,0 +1,180 @@
+use crate::*;
+
+/// Square cells on the ground plane, mapping positions to `Coord`s and back.
+#[derive(Debug, Clone, Copy)]
+pub struct Grid {
+  pub size: f32,
+  /// Whether cells are centered on multiples of `size`, rather than starting at them.
+  pub centered: bool,
+}
+
+impl Grid {
+  pub const fn new(size: f32) -> Self {
+    Self {
+      size,
+      centered: false,
+    }
+  }
+
+  pub const fn centered(size: f32) -> Self {
+    Self {
+      size,
+      centered: true,
+    }
+  }
+
+  /// The cell `v` falls in along one axis. Rounds toward negative infinity, so cells are the same
+  /// size on both sides of zero.
+  #[inline]
+  pub fn index(&self, v: f32) -> i16 {
+    let v = match self.centered {
+      true => v + self.size / 2.,
+      false => v,
+    };
+    (v / self.size).floor() as i16
+  }
+
+  #[inline]
+  pub fn coord(&self, pos: &Vec3) -> Coord {
+    Coord {
+      x: self.index(pos.x),
+      z: self.index(pos.z),
+    }
+  }
+
+  #[inline]
+  pub fn center(&self, coord: Coord) -> Vec3 {
+    let offset = match self.centered {
+      true => 0.,
+      false => self.size / 2.,
+    };
+    Vec3::new(
+      coord.x as f32 * self.size + offset,
+      0.,
+      coord.z as f32 * self.size + offset,
+    )
+  }
+
+  #[inline]
+  pub fn min(&self, coord: Coord) -> Vec3 {
+    self.center(coord) - Vec3::new(self.size / 2., 0., self.size / 2.)
+  }
+
+  /// Like `coord`, for a grid laid out relative to `origin`.
+  #[inline]
+  pub fn local_coord(&self, origin: &Transform, pos: &Vec3) -> Coord {
+    self.coord(&(origin.rotation.inverse() * (*pos - origin.translation)))
+  }
+
+  /// Like `center`, for a grid laid out relative to `origin`.
+  #[inline]
+  pub fn local_center(&self, origin: &Transform, coord: Coord) -> Vec3 {
+    origin.translation + origin.rotation * self.center(coord)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  const RUNS: usize = 10_000;
+  const GRIDS: [Grid; 4] = [
+    Grid::new(100.),
+    Grid::centered(800.),
+    Grid::centered(30.),
+    Grid::new(0.5),
+  ];
+
+  fn random_pos(rng: &mut StdRng) -> Vec3 {
+    Vec3::new(
+      rng.gen_range(-5000.0..5000.),
+      rng.gen_range(-1.0..1.),
+      rng.gen_range(-5000.0..5000.),
+    )
+  }
+
+  fn random_coord(rng: &mut StdRng) -> Coord {
+    Coord {
+      x: rng.gen_range(-100..100),
+      z: rng.gen_range(-100..100),
+    }
+  }
+
+  fn random_origin(rng: &mut StdRng) -> Transform {
+    Transform::from_translation(random_pos(rng)).with_rotation(Quat::from_rotation_y(
+      rng.gen_range(0.0..std::f32::consts::TAU),
+    ))
+  }
+
+  #[test]
+  fn index_floors() {
+    let grid = Grid::new(10.);
+    assert_eq!(grid.index(0.), 0);
+    assert_eq!(grid.index(9.9), 0);
+    assert_eq!(grid.index(-0.1), -1);
+    assert_eq!(grid.index(-10.), -1);
+    assert_eq!(grid.index(-10.1), -2);
+
+    let grid = Grid::centered(10.);
+    assert_eq!(grid.index(4.9), 0);
+    assert_eq!(grid.index(-4.9), 0);
+    assert_eq!(grid.index(-5.1), -1);
+    assert_eq!(grid.index(5.), 1);
+  }
+
+  #[test]
+  fn pos_is_inside_its_cell() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for grid in GRIDS {
+      for _ in 0..RUNS {
+        let pos = random_pos(&mut rng);
+        let min = grid.min(grid.coord(&pos));
+        let rel = pos - min;
+        // a little slack for float error right on the edges
+        let slack = grid.size * 1e-4;
+        assert!(
+          rel.x >= -slack && rel.x <= grid.size + slack,
+          "{:?} {:?}",
+          grid,
+          pos
+        );
+        assert!(
+          rel.z >= -slack && rel.z <= grid.size + slack,
+          "{:?} {:?}",
+          grid,
+          pos
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn center_round_trips() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for grid in GRIDS {
+      for _ in 0..RUNS {
+        let coord = random_coord(&mut rng);
+        assert_eq!(grid.coord(&grid.center(coord)), coord);
+      }
+    }
+  }
+
+  #[test]
+  fn rotated_round_trips() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for grid in GRIDS {
+      for _ in 0..RUNS {
+        let origin = random_origin(&mut rng);
+        let coord = random_coord(&mut rng);
+        let center = grid.local_center(&origin, coord);
+        assert_eq!(grid.local_coord(&origin, &center), coord);
+
+        let pos = random_pos(&mut rng);
+        let center = grid.local_center(&origin, grid.local_coord(&origin, &pos));
+        let dist = Vec3::new(pos.x - center.x, 0., pos.z - center.z).length();
+        assert!(dist <= grid.size * std::f32::consts::FRAC_1_SQRT_2 * 1.001);
+      }
+    }
+  }
+}
@@ -1,6 +1,30 @@
+pub mod asset_cache;
+pub use asset_cache::{AssetCache, MeshKey};
+pub mod bake;
+pub use bake::{BakeBuildings, Baker, Part};
+pub mod chunks;
+pub use chunks::{ChunkState, Chunks};
+pub mod console;
+pub use console::{Console, ConsoleCommands};
+pub mod controls;
+pub use controls::{Action, Actions, Aim, Controls};
+pub mod fog_of_war;
+pub use fog_of_war::FogOfWar;
+pub mod game_state;
+pub use game_state::{GameState, WorldSeed};
+pub mod grid;
+pub use grid::Grid;
 pub mod nav;
-pub use nav::{NavNode, NavNodeType, Navigator};
+pub use nav::{NavGraph, NavNode, NavNodeType, Navigator, PathQueue};
+pub mod save;
+pub use save::{PendingLoad, Save, ZombieSave};
+pub mod salt;
+pub use salt::Salt;
 pub mod rect;
 pub use rect::Rect;
+pub mod time_of_day;
+pub use time_of_day::{Sun, TimeOfDay};
+pub mod weather;
+pub use weather::{RainDrop, Weather, WeatherState};
 pub mod zones;
-pub use zones::{ZItem, Zones, ZONE_TX};
+pub use zones::Zones;
@@ -1,9 +1,14 @@
-use rand::{thread_rng, Rng};
-
 use crate::*;
-use std::hash::{Hash, Hasher};
+use bevy::ecs::system::SystemParam;
+use std::{
+  collections::VecDeque,
+  hash::{Hash, Hasher},
+  marker::PhantomData,
+};
 
 static NAV_ID: AtomicUsize = AtomicUsize::new(0);
+/// How many paths are worked out a frame at most, the rest wait their turn.
+const PATHS_PER_FRAME: usize = 8;
 
 pub struct Navigator {
   traversed: HashSet<usize>,
@@ -20,40 +25,51 @@ impl Navigator {
     }
   }
 
-  pub fn nav(&mut self) {
-    let last = match self.path.last() {
-      Some(last) if last.id == self.dest.id => {
-        return;
-      }
-      Some(last) => last,
-      None => {
-        // no path?
-        return;
-      }
-    };
+  /// Whether `nav` got all the way to the destination.
+  pub fn found(&self) -> bool {
+    self.path.last().is_some_and(|last| last.id == self.dest.id)
+  }
 
-    let mut choice = None;
-    let mut choice_dist = f32::MAX;
-    for adj in &*last.adj.read() {
-      if self.traversed.contains(&adj.id) {
-        continue;
+  /// Walks towards the destination, backing out of dead ends. Buildings are linked outside, so
+  /// this can cover every loaded building and is a loop rather than recursion.
+  pub fn nav(&mut self) {
+    loop {
+      let last = match self.path.last() {
+        Some(last) if last.id == self.dest.id => {
+          return;
+        }
+        Some(last) => last,
+        None => {
+          // no path?
+          return;
+        }
+      };
+
+      let mut choice: Option<Arc<NavNode>> = None;
+      let mut choice_dist = f32::MAX;
+      for adj in &*last.adj.read() {
+        if self.traversed.contains(&adj.id) {
+          continue;
+        }
+
+        let dist = adj.pos.distance(self.dest.pos);
+        // ties go to the older node, so the same graph always gives the same path
+        if dist < choice_dist
+          || dist == choice_dist && choice.as_ref().is_some_and(|c| adj.id < c.id)
+        {
+          choice = Some(adj.clone());
+          choice_dist = dist;
+        }
       }
 
-      let dist = adj.pos.distance(self.dest.pos);
-      if dist < choice_dist {
-        choice = Some(adj.clone());
-        choice_dist = dist;
+      if let Some(choice) = choice {
+        self.traversed.insert(choice.id);
+        self.path.push(choice);
+      } else {
+        // no new choice found - walk it back
+        self.path.pop();
       }
     }
-
-    if let Some(choice) = choice {
-      self.traversed.insert(choice.id);
-      self.path.push(choice);
-    } else {
-      // no new choice found - walk it back
-      self.path.pop();
-    }
-    self.nav();
   }
 }
 
@@ -67,6 +83,11 @@ pub struct NavNode {
 }
 
 impl NavNode {
+  /// Unique, and larger for nodes made later.
+  pub fn id(&self) -> usize {
+    self.id
+  }
+
   pub fn new(pos: Vec3, r#type: NavNodeType, area: Rect, adj: HashSet<Arc<Self>>) -> Arc<Self> {
     Arc::new(Self {
       id: NAV_ID.fetch_add(1, Ordering::SeqCst),
@@ -95,57 +116,124 @@ impl Hash for NavNode {
   }
 }
 
-#[derive(Component)]
-pub struct NavNodeComponent {
-  pub node: Arc<NavNode>,
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NavNodeType {
+  #[default]
+  Other,
+  Cell,
+  Door,
+  Outside,
+}
+
+/// The navigation of every loaded building, looked up through the nav nodes indexed in `Zones`.
+/// The nodes already live in `Zones`, linked across buildings as they're indexed, so this is a
+/// view over it rather than a resource of its own that would have to follow every chunk load.
+#[derive(SystemParam)]
+pub struct NavGraph<'w, 's> {
+  zones: Res<'w, Zones>,
+  #[system_param(ignore)]
+  marker: PhantomData<&'s ()>,
 }
 
-impl NavNodeComponent {
-  pub fn fabricate(
-    self,
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-  ) -> Entity {
-    let mut rng = thread_rng();
-    let r: u8 = rng.gen();
-    let g: u8 = rng.gen();
-    let b: u8 = rng.gen();
-    let t = self.node.pos;
-
-    let var = 1.;
-
-    let line_up = Vec3::new(rng.gen_range(-var..var), 8., rng.gen_range(-var..var));
-
-    for adj in &*self.node.adj.read() {
-      DebugLine::build(t + line_up, adj.pos + line_up, Color::rgb_u8(r, g, b))
-        .fabricate(commands, meshes, materials);
+impl<'w, 's> NavGraph<'w, 's> {
+  /// The node a path from `pos` should start at. Inside a building that's the door or cell `pos`
+  /// is standing in, anywhere else (yards, roads) it's the nearest node outside a door. `None`
+  /// only if there is no building nearby.
+  pub fn nearest_reachable_node(&self, pos: &Vec3) -> Option<Arc<NavNode>> {
+    if let Some(node) = self.node_containing(pos) {
+      return Some(node);
+    }
+
+    // from outside, buildings can only be entered through their doors
+    let inside = self.zones.cell_at(pos).is_some();
+    self
+      .zones
+      .nearest_nav_node_matching(pos, |node| inside || node.r#type == NavNodeType::Outside)
+      .cloned()
+  }
+
+  /// The node whose area `pos` is in. Doorways overlap the cells on either side, so the door
+  /// wins there.
+  fn node_containing(&self, pos: &Vec3) -> Option<Arc<NavNode>> {
+    let Coord { x, z } = Zones::translation_to_coord(pos);
+    let mut containing: Option<&Arc<NavNode>> = None;
+
+    // areas are smaller than a zone, but can reach over into the next one
+    for dz in -1..=1 {
+      for dx in -1..=1 {
+        let coord = Coord {
+          x: x + dx,
+          z: z + dz,
+        };
+        let zone = match self.zones.zones.get(&coord) {
+          Some(zone) => zone,
+          None => continue,
+        };
+
+        for node in zone.nav_nodes.iter().filter(|node| node.area.contains(pos)) {
+          let better = match containing {
+            None => true,
+            Some(c) if node.r#type == c.r#type => node.id < c.id,
+            Some(_) => node.r#type == NavNodeType::Door,
+          };
+          if better {
+            containing = Some(node);
+          }
+        }
+      }
     }
 
-    commands
-      .spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Icosphere {
-          radius: 2.,
-          subdivisions: 10,
-        })),
-        material: materials.add(StandardMaterial {
-          base_color: Color::rgb_u8(r, g, b),
-          unlit: true,
-          ..default()
-        }),
-        transform: Transform::from_translation(t + Vec3::new(0., 3., 0.)),
-        ..default()
-      })
-      .insert(self)
-      .id()
+    containing.cloned()
+  }
+
+  /// A path from `from` to `to`, in the order to walk it. Empty if they aren't connected.
+  pub fn path(&self, from: &Vec3, to: &Vec3) -> Vec<Arc<NavNode>> {
+    let (from, to) = match (
+      self.nearest_reachable_node(from),
+      self.nearest_reachable_node(to),
+    ) {
+      (Some(from), Some(to)) => (from, to),
+      _ => return vec![],
+    };
+
+    let mut nav = Navigator::new(&from, &to);
+    nav.nav();
+    if !nav.found() {
+      return vec![];
+    }
+    nav.path
   }
 }
 
-#[derive(Debug, Default)]
-pub enum NavNodeType {
-  #[default]
-  Other,
-  Cell,
-  Door,
-  Outside,
+/// Zombies waiting for a path to the player. Worked through a few a frame, so a horde noticing the
+/// player all at once doesn't stall the game.
+#[derive(Default)]
+pub struct PathQueue {
+  pending: VecDeque<Entity>,
+}
+
+impl PathQueue {
+  pub fn request(&mut self, entity: Entity) {
+    if !self.pending.contains(&entity) {
+      self.pending.push_back(entity);
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.pending.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pending.is_empty()
+  }
+
+  /// The requests to work out this frame, longest waiting first.
+  pub fn take(&mut self) -> Vec<Entity> {
+    let n = self.pending.len().min(PATHS_PER_FRAME);
+    self.pending.drain(..n).collect()
+  }
+
+  pub fn reset(mut this: ResMut<Self>) {
+    this.pending.clear();
+  }
 }
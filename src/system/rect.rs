@@ -2,14 +2,20 @@ use rand::{thread_rng, Rng};
 
 use crate::*;
 
+/// A rectangle on the ground plane. The extents are before `rotation` is applied about `pivot`,
+/// so a rect can face any direction.
 #[derive(Debug)]
 pub struct Rect {
   z_min: f32,
   z_max: f32,
   x_min: f32,
   x_max: f32,
+  /// Along x.
   w: f32,
+  /// Along z.
   h: f32,
+  pivot: Vec3,
+  rotation: Quat,
 }
 
 pub struct RectBuilder {
@@ -24,8 +30,10 @@ impl Rect {
       z_max,
       x_min,
       x_max,
-      w: z_max - z_min,
-      h: x_max - x_min,
+      w: x_max - x_min,
+      h: z_max - z_min,
+      pivot: Vec3::ZERO,
+      rotation: Quat::IDENTITY,
     }
   }
 
@@ -33,26 +41,95 @@ impl Rect {
     RectBuilder { w, h }
   }
 
+  /// Turns the rect by `rotation` around `pivot`, replacing any rotation it had.
+  pub fn rotated_about(mut self, pivot: Vec3, rotation: Quat) -> Self {
+    self.pivot = pivot;
+    self.rotation = rotation;
+    self
+  }
+
+  #[inline]
+  fn to_local(&self, t: &Vec3) -> Vec3 {
+    self.pivot + self.rotation.inverse() * (*t - self.pivot)
+  }
+
+  #[inline]
+  fn to_world(&self, t: &Vec3) -> Vec3 {
+    self.pivot + self.rotation * (*t - self.pivot)
+  }
+
+  /// In order around the rect.
+  pub fn corners(&self) -> [Vec3; 4] {
+    [
+      Vec3::new(self.x_max, 0., self.z_min),
+      Vec3::new(self.x_max, 0., self.z_max),
+      Vec3::new(self.x_min, 0., self.z_max),
+      Vec3::new(self.x_min, 0., self.z_min),
+    ]
+    .map(|c| self.to_world(&c))
+  }
+
   pub fn contains(&self, t: &Vec3) -> bool {
+    let t = self.to_local(t);
     t.z >= self.z_min && t.z <= self.z_max && t.x >= self.x_min && t.x <= self.x_max
   }
 
+  /// Whether the rects share any ground, touching included. They don't if there is a line
+  /// between them, which would run along a side of one of them.
+  pub fn overlaps(&self, other: &Rect) -> bool {
+    let (corners, other_corners) = (self.corners(), other.corners());
+    let axes = [
+      self.rotation * Vec3::X,
+      self.rotation * Vec3::Z,
+      other.rotation * Vec3::X,
+      other.rotation * Vec3::Z,
+    ];
+    let span = |corners: &[Vec3; 4], axis: Vec3| {
+      corners.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+        let d = c.dot(axis);
+        (min.min(d), max.max(d))
+      })
+    };
+
+    axes.into_iter().all(|axis| {
+      let (min, max) = span(&corners, axis);
+      let (other_min, other_max) = span(&other_corners, axis);
+      min <= other_max && max >= other_min
+    })
+  }
+
+  /// Min corner of the bounding box.
+  pub fn min(&self) -> Vec3 {
+    self
+      .corners()
+      .into_iter()
+      .fold(Vec3::splat(f32::MAX), Vec3::min)
+  }
+
+  /// Max corner of the bounding box.
+  pub fn max(&self) -> Vec3 {
+    self
+      .corners()
+      .into_iter()
+      .fold(Vec3::splat(f32::MIN), Vec3::max)
+  }
+
   fn center(&self) -> Vec3 {
-    Vec3::new(
+    self.to_world(&Vec3::new(
       (self.x_min + self.x_max) / 2.,
       0.,
       (self.z_min + self.z_max) / 2.,
-    )
+    ))
   }
 
   pub fn random(&self, padding: f32) -> Vec3 {
-    if padding * 2. >= self.w && self.w <= self.h {
+    if padding * 2. >= self.w.min(self.h) {
       return self.center();
     }
     let mut rng = thread_rng();
     let z = rng.gen_range((self.z_min + padding)..(self.z_max - padding));
     let x = rng.gen_range((self.x_min + padding)..(self.x_max - padding));
-    Vec3::new(x, 0., z)
+    self.to_world(&Vec3::new(x, 0., z))
   }
 
   pub fn fabricate_debug_walls(
@@ -60,44 +137,77 @@ impl Rect {
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    cache: &mut ResMut<AssetCache>,
+    ass: &Res<AssetServer>,
   ) {
-    let c1 = Vec3::new(self.x_max, 0., self.z_min);
-    let c2 = Vec3::new(self.x_max, 0., self.z_max);
-    let c3 = Vec3::new(self.x_min, 0., self.z_max);
-    let c4 = Vec3::new(self.x_min, 0., self.z_min);
-    Wall::build(c1.clone(), c2.clone(), wall::State::Solid).fabricate(commands, meshes, materials);
-    Wall::build(c2, c3.clone(), wall::State::Solid).fabricate(commands, meshes, materials);
-    Wall::build(c3, c4.clone(), wall::State::Solid).fabricate(commands, meshes, materials);
-    Wall::build(c4, c1, wall::State::Solid).fabricate(commands, meshes, materials);
+    let [c1, c2, c3, c4] = self.corners();
+    Wall::build(c1, c2, wall::State::Solid).fabricate(commands, meshes, materials, cache, ass);
+    Wall::build(c2, c3, wall::State::Solid).fabricate(commands, meshes, materials, cache, ass);
+    Wall::build(c3, c4, wall::State::Solid).fabricate(commands, meshes, materials, cache, ass);
+    Wall::build(c4, c1, wall::State::Solid).fabricate(commands, meshes, materials, cache, ass);
   }
 }
 
 impl RectBuilder {
   pub fn enter_south_middle_at(&self, t: &Vec3) -> Rect {
     let x_min = t.x - CELL_SIZE_2;
-    let z_min = t.z - self.w / 2.;
+    let z_min = t.z - self.h / 2.;
 
     Rect {
       x_min,
-      x_max: x_min + self.h,
+      x_max: x_min + self.w,
       z_min,
-      z_max: z_min + self.w,
+      z_max: z_min + self.h,
       w: self.w,
       h: self.h,
+      pivot: Vec3::ZERO,
+      rotation: Quat::IDENTITY,
     }
   }
 
   pub fn center_at(&self, t: &Vec3) -> Rect {
-    let x_min = t.x - self.h / 2.;
-    let z_min = t.z - self.w / 2.;
+    let x_min = t.x - self.w / 2.;
+    let z_min = t.z - self.h / 2.;
 
     Rect {
       x_min,
-      x_max: x_min + self.h,
+      x_max: x_min + self.w,
       z_min,
-      z_max: z_min + self.w,
+      z_max: z_min + self.h,
       w: self.w,
       h: self.h,
+      pivot: Vec3::ZERO,
+      rotation: Quat::IDENTITY,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::f32::consts::FRAC_PI_4;
+
+  fn diamond(center: Vec3) -> Rect {
+    Rect::build(10., 10.)
+      .center_at(&center)
+      .rotated_about(center, Quat::from_rotation_y(FRAC_PI_4))
+  }
+
+  #[test]
+  fn turned_rects_overlap_by_shape() {
+    let a = diamond(Vec3::ZERO);
+    // bounding boxes overlap, the diamonds are 17 apart along the diagonal
+    let apart = diamond(Vec3::new(12., 0., 12.));
+    assert!(a.max().x > apart.min().x && a.max().z > apart.min().z);
+    assert!(!a.overlaps(&apart));
+    assert!(!apart.overlaps(&a));
+
+    let close = diamond(Vec3::new(4., 0., 4.));
+    assert!(a.overlaps(&close) && close.overlaps(&a));
+
+    let square = Rect::build(2., 2.).center_at(&Vec3::new(8.5, 0., 0.));
+    assert!(!a.overlaps(&square));
+    let square = Rect::build(2., 2.).center_at(&Vec3::new(7.5, 0., 0.));
+    assert!(a.overlaps(&square));
+  }
+}
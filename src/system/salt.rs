@@ -0,0 +1,20 @@
+/// Xored into a seed so each thing drawn from it gets an rng of its own, and changing how one of
+/// them uses its rng doesn't change any of the others. The compiler keeps them distinct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Salt {
+  // per road grid cell, through `RoadGrid::cell_rng`
+  RoadX = 0,
+  RoadZ = 1,
+  Lot = 2,
+  // per building
+  Siding = 0x51d1,
+  Zombies = 0x2b1e,
+  Wallpaper = 0x3a11,
+  Yard = 0x7a4d,
+  Power = 0x90e7,
+  PowerPlacement = 0x91ac,
+  // per world
+  Ai = 0xa1,
+  Weather = 0x3e7a,
+}
@@ -0,0 +1,456 @@
+use crate::{building::WallChange, chunks::ZombieQuery, road::RoadGrid, *};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+pub const SAVE_VERSION: u32 = 2;
+const QUICKSAVE_PATH: &str = "saves/quicksave.ron";
+
+/// Everything needed to rebuild a world: generation seeds, plus whatever changed since.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Save {
+  pub version: u32,
+  pub seed: u64,
+  /// Chunks that have been generated. Anything else is generated fresh from the seed.
+  pub chunks: Vec<ChunkSave>,
+  pub player: PlayerSave,
+  /// Squares of the map the player has explored. Missing from older saves.
+  #[serde(default)]
+  pub explored: Vec<Coord>,
+  /// The time of day. Missing from older saves, which start at the usual hour.
+  #[serde(default)]
+  pub hour: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkSave {
+  pub coord: Coord,
+  pub state: ChunkState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSave {
+  pub translation: Vec3,
+  pub angle: f32,
+  pub health: f32,
+  pub flashlight: FlashlightSave,
+  /// Missing from older saves.
+  #[serde(default)]
+  pub kills: u32,
+  /// Missing from older saves, which keep the loaded gun the player starts with.
+  #[serde(default)]
+  pub weapon: Option<WeaponSave>,
+  /// Missing from older saves, which start rested.
+  #[serde(default)]
+  pub stamina: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlashlightSave {
+  pub on: bool,
+  pub battery: f32,
+  pub spares: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponSave {
+  pub loaded: u32,
+  /// Seconds of the reload left, 0 when not reloading.
+  pub reloading: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZombieSave {
+  pub translation: Vec3,
+  pub health: f32,
+  pub aggressive: bool,
+  /// Where it was heading. Missing from older saves.
+  #[serde(default)]
+  pub dest: Option<Vec3>,
+  /// Seconds it had left to be stunned for. Missing from older saves.
+  #[serde(default)]
+  pub stunned_for: Option<f64>,
+}
+
+/// Version 1, from before the world was split into chunks. Only the fields still needed are
+/// read, roads and building seeds are regenerated.
+#[derive(Deserialize)]
+struct SaveV1 {
+  seed: u64,
+  buildings: Vec<BuildingSaveV1>,
+  player: PlayerSave,
+  zombies: Vec<ZombieSave>,
+}
+
+#[derive(Deserialize)]
+struct BuildingSaveV1 {
+  translation: Vec3,
+  wall_changes: Vec<WallChange>,
+}
+
+/// Just enough of a save to know how to parse the rest of it.
+#[derive(Deserialize)]
+struct SaveHeader {
+  version: u32,
+}
+
+/// A save waiting to be rebuilt by the `Loading` state.
+#[derive(Default)]
+pub struct PendingLoad(pub Option<Save>);
+
+impl ZombieSave {
+  /// A zombie that has yet to be spawned for the first time.
+  pub fn fresh(translation: Vec3) -> Self {
+    Self {
+      translation,
+      health: 1.,
+      aggressive: true,
+      dest: None,
+      stunned_for: None,
+    }
+  }
+
+  /// `now` is the game time, a stun is saved as however long it has left.
+  pub fn new(t: &Transform, health: &Health, aggressive: bool, zombie: &Zombie, now: f64) -> Self {
+    Self {
+      translation: t.translation,
+      health: health.health(),
+      aggressive,
+      dest: Some(zombie.dest()),
+      stunned_for: zombie
+        .stunned_until()
+        .map(|until| until - now)
+        .filter(|left| *left > 0.),
+    }
+  }
+
+  pub fn spawn(
+    &self,
+    now: f64,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    cache: &mut ResMut<AssetCache>,
+    materials: &mut ResMut<Assets<ZombieMaterial>>,
+  ) -> Option<Entity> {
+    let id = Zombie::fabricate(self.translation, commands, meshes, cache, materials)?;
+
+    let mut health = Health::new(Color::rgb(1., 0., 0.));
+    health.set_health(self.health);
+
+    let mut ec = commands.entity(id);
+    ec.insert(health);
+    ec.insert(Zombie::resumed(
+      self.dest.unwrap_or(self.translation),
+      self.stunned_for.map(|left| now + left),
+    ));
+    if !self.aggressive {
+      ec.remove::<Aggressive>();
+    }
+    Some(id)
+  }
+}
+
+/// Version 1 only ever had the one building at the origin, which is now the first building of
+/// the chunk there. Its zombies are handed to whichever chunk they were standing in.
+impl From<SaveV1> for Save {
+  fn from(v1: SaveV1) -> Self {
+    let mut states: HashMap<Coord, ChunkState> = HashMap::new();
+    for zombie in v1.zombies {
+      states
+        .entry(RoadGrid::coord(&zombie.translation))
+        .or_default()
+        .zombies
+        .push(zombie);
+    }
+    for building in v1.buildings {
+      states
+        .entry(RoadGrid::coord(&building.translation))
+        .or_default()
+        .wall_changes
+        .push(building.wall_changes);
+    }
+
+    Self {
+      version: SAVE_VERSION,
+      seed: v1.seed,
+      chunks: states
+        .into_iter()
+        .sorted_by_key(|(coord, _)| *coord)
+        .map(|(coord, state)| ChunkSave { coord, state })
+        .collect(),
+      player: v1.player,
+      explored: vec![],
+      hour: None,
+    }
+  }
+}
+
+impl Save {
+  pub fn to_ron(&self) -> Result<String, ron::Error> {
+    ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+  }
+
+  /// Parses a save of any known version, migrating older formats to the current one.
+  pub fn from_ron(s: &str) -> Result<Self, String> {
+    let header: SaveHeader = ron::from_str(s).map_err(|e| e.to_string())?;
+
+    match header.version {
+      SAVE_VERSION => ron::from_str(s).map_err(|e| e.to_string()),
+      1 => ron::from_str::<SaveV1>(s)
+        .map(Save::from)
+        .map_err(|e| e.to_string()),
+      v => Err(format!(
+        "save version {} is not supported (expected at most {})",
+        v, SAVE_VERSION
+      )),
+    }
+  }
+
+  pub fn quicksave(
+    actions: Res<Actions>,
+    time: Res<Time>,
+    seed: Res<WorldSeed>,
+    chunks: Res<Chunks>,
+    memory: Res<MapMemory>,
+    kills: Res<Kills>,
+    time_of_day: Res<TimeOfDay>,
+    player_query: Query<(&Transform, &Health, &Player, &Weapon, &Stamina)>,
+    flashlight_query: Query<&Flashlight>,
+    zombies: ZombieQuery,
+  ) {
+    if !actions.just_pressed(Action::QuickSave) || player_query.is_empty() {
+      return;
+    }
+
+    let (pt, health, player, weapon, stamina) = player_query.single();
+    let flashlight = flashlight_query.single();
+
+    let save = Save {
+      version: SAVE_VERSION,
+      seed: seed.0,
+      chunks: chunks
+        .states(&zombies, time.seconds_since_startup())
+        .into_iter()
+        .map(|(coord, state)| ChunkSave { coord, state })
+        .collect(),
+      player: PlayerSave {
+        translation: pt.translation,
+        angle: player.angle,
+        health: health.health(),
+        flashlight: FlashlightSave {
+          on: flashlight.on,
+          battery: flashlight.battery,
+          spares: flashlight.spares,
+        },
+        kills: kills.0,
+        weapon: Some(WeaponSave {
+          loaded: weapon.loaded,
+          reloading: weapon.reload_left(),
+        }),
+        stamina: Some(stamina.stamina),
+      },
+      explored: memory.explored.iter().copied().sorted().collect(),
+      hour: Some(time_of_day.hour),
+    };
+
+    let result = save.to_ron().map_err(|e| e.to_string()).and_then(|ron| {
+      std::fs::create_dir_all("saves").map_err(|e| e.to_string())?;
+      std::fs::write(QUICKSAVE_PATH, ron).map_err(|e| e.to_string())
+    });
+    match result {
+      Ok(_) => println!("Saved to {}", QUICKSAVE_PATH),
+      Err(err) => println!("Could not save to {}: {}", QUICKSAVE_PATH, err),
+    }
+  }
+
+  pub fn quickload(
+    actions: Res<Actions>,
+    mut load: ResMut<PendingLoad>,
+    mut state: ResMut<State<GameState>>,
+  ) {
+    if !actions.just_pressed(Action::QuickLoad) || *state.current() == GameState::Loading {
+      return;
+    }
+
+    let save = std::fs::read_to_string(QUICKSAVE_PATH)
+      .map_err(|e| e.to_string())
+      .and_then(|s| Self::from_ron(&s));
+    match save {
+      Ok(save) => {
+        load.0 = Some(save);
+        let _ = state.replace(GameState::Loading);
+      }
+      Err(err) => println!("Could not load {}: {}", QUICKSAVE_PATH, err),
+    }
+  }
+
+  /// Applies the pending save on top of the freshly generated world. `Chunks` generates the
+  /// world around the player from the seed afterwards, using the saved chunk states.
+  pub fn restore(
+    mut load: ResMut<PendingLoad>,
+    mut chunks: ResMut<Chunks>,
+    mut memory: ResMut<MapMemory>,
+    mut kills: ResMut<Kills>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut player_query: Query<(
+      &mut Transform,
+      &mut Health,
+      &mut Player,
+      &mut Weapon,
+      &mut Stamina,
+    )>,
+    mut flashlight_query: Query<&mut Flashlight>,
+  ) {
+    // wait for the player spawned when entering `Loading` to show up
+    if player_query.is_empty() {
+      return;
+    }
+    let save = match load.0.take() {
+      Some(save) => save,
+      None => return,
+    };
+
+    let (mut pt, mut health, mut player, mut weapon, mut stamina) = player_query.single_mut();
+    pt.translation = save.player.translation;
+    pt.rotation = Quat::from_axis_angle(Vec3::Y, save.player.angle);
+    player.angle = save.player.angle;
+    health.set_health(save.player.health);
+    kills.0 = save.player.kills;
+    if let Some(saved) = &save.player.weapon {
+      weapon.restore(saved.loaded, saved.reloading);
+    }
+    if let Some(saved) = save.player.stamina {
+      stamina.stamina = saved.clamp(0., 1.);
+    }
+    if let Some(hour) = save.hour {
+      time_of_day.hour = hour.rem_euclid(24.);
+    }
+
+    for mut flashlight in &mut flashlight_query {
+      flashlight.on = save.player.flashlight.on;
+      flashlight.battery = save.player.flashlight.battery;
+      flashlight.spares = save.player.flashlight.spares;
+    }
+
+    chunks.saved = save
+      .chunks
+      .into_iter()
+      .map(|chunk| (chunk.coord, chunk.state))
+      .collect();
+    memory.explored = save.explored.into_iter().collect();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn zombie(x: f32, stunned_for: Option<f64>) -> ZombieSave {
+    ZombieSave {
+      translation: Vec3::new(x, 1., -x),
+      health: 0.5,
+      aggressive: false,
+      dest: Some(Vec3::new(x + 10., 0., 3.)),
+      stunned_for,
+    }
+  }
+
+  #[test]
+  fn saves_round_trip() {
+    let save = Save {
+      version: SAVE_VERSION,
+      seed: 42,
+      chunks: vec![ChunkSave {
+        coord: Coord { x: -1, z: 2 },
+        state: ChunkState {
+          zombies: vec![zombie(-900., Some(1.5)), zombie(-850., None)],
+          wall_changes: vec![vec![WallChange {
+            coord: Coord { x: 1, z: 0 },
+            dir: 2,
+            state: wall::State::Door,
+          }]],
+          power: vec![],
+          seen_rooms: vec![vec![0, 3]],
+        },
+      }],
+      player: PlayerSave {
+        translation: Vec3::new(5., 1., 6.),
+        angle: 0.25,
+        health: 0.75,
+        flashlight: FlashlightSave {
+          on: true,
+          battery: 0.5,
+          spares: 2,
+        },
+        kills: 7,
+        weapon: Some(WeaponSave {
+          loaded: 4,
+          reloading: 0.5,
+        }),
+        stamina: Some(0.25),
+      },
+      explored: vec![Coord { x: 0, z: 0 }, Coord { x: 1, z: 0 }],
+      hour: Some(3.5),
+    };
+
+    let ron = save.to_ron().unwrap();
+    let loaded = Save::from_ron(&ron).unwrap();
+    assert_eq!(loaded.to_ron().unwrap(), ron);
+
+    let zombies = &loaded.chunks[0].state.zombies;
+    assert_eq!(zombies[0].dest, Some(Vec3::new(-890., 0., 3.)));
+    assert_eq!(zombies[0].stunned_for, Some(1.5));
+    assert_eq!(zombies[1].stunned_for, None);
+    let weapon = loaded.player.weapon.as_ref().unwrap();
+    assert_eq!((weapon.loaded, weapon.reloading), (4, 0.5));
+    assert_eq!(loaded.player.stamina, Some(0.25));
+    assert_eq!(loaded.hour, Some(3.5));
+  }
+
+  #[test]
+  fn version_1_saves_migrate() {
+    let v1 = r#"(
+      version: 1,
+      seed: 9,
+      buildings: [
+        (
+          translation: (0.0, 0.1, 0.0),
+          wall_changes: [(coord: (x: 0, z: 1), dir: 1, state: Window)],
+        ),
+      ],
+      player: (
+        translation: (3.0, 1.0, 4.0),
+        angle: 1.0,
+        health: 0.5,
+        flashlight: (on: false, battery: 0.25, spares: 1),
+      ),
+      zombies: [
+        (translation: (10.0, 1.0, 10.0), health: 1.0, aggressive: true),
+        (translation: (-10.0, 1.0, -10.0), health: 0.5, aggressive: false),
+      ],
+    )"#;
+
+    let save = Save::from_ron(v1).unwrap();
+    assert_eq!(save.version, SAVE_VERSION);
+    assert_eq!(save.seed, 9);
+    assert_eq!(save.player.kills, 0);
+    assert!(save.player.weapon.is_none() && save.player.stamina.is_none());
+    assert_eq!(save.hour, None);
+    assert_eq!(save.player.translation, Vec3::new(3., 1., 4.));
+
+    let zombies = save
+      .chunks
+      .iter()
+      .flat_map(|chunk| &chunk.state.zombies)
+      .collect::<Vec<_>>();
+    assert_eq!(zombies.len(), 2);
+    assert!(zombies
+      .iter()
+      .all(|z| z.dest.is_none() && z.stunned_for.is_none()));
+
+    let home = RoadGrid::coord(&Vec3::new(0., 0.1, 0.));
+    let chunk = save.chunks.iter().find(|c| c.coord == home).unwrap();
+    assert_eq!(chunk.state.wall_changes[0][0].state, wall::State::Window);
+    // and it saves again as the current version
+    assert!(Save::from_ron(&save.to_ron().unwrap()).is_ok());
+  }
+}
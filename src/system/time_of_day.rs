@@ -0,0 +1,147 @@
+use crate::*;
+
+/// Real seconds in one in-game day.
+const DAY_LENGTH: f32 = 600.;
+/// Hour of the day a new world starts at, just before dusk.
+const START_HOUR: f32 = 18.;
+const DAY_ILLUMINANCE: f32 = 20000.;
+const DAY_AMBIENT: f32 = 0.4;
+const NIGHT_AMBIENT: f32 = 0.02;
+const DAY_SKY: Color = Color::rgb(0.5, 0.7, 0.9);
+const DUSK_SKY: Color = Color::rgb(0.8, 0.45, 0.3);
+const NIGHT_SKY: Color = Color::rgb(0.02, 0.02, 0.05);
+/// Street lamps are on while the daylight is below this.
+const LAMPS_ON: f32 = 0.3;
+/// How much further zombies can see in the middle of the night than in the middle of the day.
+const NIGHT_SIGHT: f32 = 1.5;
+/// The share of cells a zombie starts in when the sun is high, at night it's every one.
+const DAY_SPAWN_RATE: f32 = 0.5;
+
+/// The in-game clock, driving the sun, ambient light, sky and street lamps.
+pub struct TimeOfDay {
+  /// 0 to 24.
+  pub hour: f32,
+}
+
+impl Default for TimeOfDay {
+  fn default() -> Self {
+    Self { hour: START_HOUR }
+  }
+}
+
+/// The light the sun shines.
+#[derive(Component)]
+pub struct Sun;
+
+impl TimeOfDay {
+  /// How far around the sky the sun is, 0 at sunrise and PI at sunset.
+  fn sun_angle(&self) -> f32 {
+    (self.hour - 6.) / 24. * std::f32::consts::TAU
+  }
+
+  /// 0 at night to 1 when the sun is high, fading through dawn and dusk.
+  pub fn daylight(&self) -> f32 {
+    let height = self.sun_angle().sin();
+    ((height + 0.1) / 0.4).clamp(0., 1.)
+  }
+
+  pub fn lamps_on(&self) -> bool {
+    self.daylight() < LAMPS_ON
+  }
+
+  /// What zombie perception ranges are multiplied by, they see further in the dark.
+  pub fn zombie_sight(&self) -> f32 {
+    1. + (NIGHT_SIGHT - 1.) * (1. - self.daylight())
+  }
+
+  /// The share of a building's cells zombies start in, more of them come out in the dark.
+  pub fn zombie_spawn_rate(&self) -> f32 {
+    DAY_SPAWN_RATE + (1. - DAY_SPAWN_RATE) * (1. - self.daylight())
+  }
+
+  fn sky(&self) -> Color {
+    let daylight = self.daylight();
+    // dusk and dawn in between night and day, on the way to both
+    let (from, to, t) = match daylight < 0.5 {
+      true => (NIGHT_SKY, DUSK_SKY, daylight * 2.),
+      false => (DUSK_SKY, DAY_SKY, daylight * 2. - 1.),
+    };
+    Color::rgb(
+      from.r() + (to.r() - from.r()) * t,
+      from.g() + (to.g() - from.g()) * t,
+      from.b() + (to.b() - from.b()) * t,
+    )
+  }
+
+  pub fn setup(mut commands: Commands) {
+    commands
+      .spawn_bundle(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+          illuminance: 0.,
+          ..default()
+        },
+        ..default()
+      })
+      .insert(Sun);
+  }
+
+  /// A fresh world starts at the same time of day, a loaded one gets its hour back in
+  /// `Save::restore`.
+  pub fn reset(mut this: ResMut<Self>) {
+    *this = Self::default();
+  }
+
+  pub fn update(
+    time: Res<Time>,
+    mut this: ResMut<Self>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    mut sun_query: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    mut lamp_query: Query<&mut Visibility, With<road::StreetLampLight>>,
+  ) {
+    this.hour = (this.hour + time.delta_seconds() / DAY_LENGTH * 24.) % 24.;
+    let daylight = this.daylight();
+
+    let angle = this.sun_angle();
+    // from the sun toward the ground, a little off to the side so it's never straight down
+    let dir = -Vec3::new(angle.cos(), angle.sin(), 0.3).normalize();
+    for (mut light, mut transform) in &mut sun_query {
+      light.illuminance = DAY_ILLUMINANCE * daylight;
+      *transform = Transform::default().looking_at(dir, Vec3::Y);
+    }
+
+    ambient.brightness = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight;
+    clear_color.0 = this.sky();
+
+    let lamps_on = this.lamps_on();
+    for mut visibility in &mut lamp_query {
+      if visibility.is_visible != lamps_on {
+        visibility.is_visible = lamps_on;
+      }
+    }
+  }
+}
+
+impl ConsoleCommands for TimeOfDay {
+  fn register(console: &mut Console) {
+    console.command("time", "time hh:mm", "Sets the clock", Self::set_time);
+  }
+}
+
+impl TimeOfDay {
+  fn set_time(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let usage = "Usage: time hh:mm";
+    let (hours, minutes) = args
+      .first()
+      .and_then(|time| time.split_once(':'))
+      .ok_or(usage)?;
+    let hours: u32 = hours.parse().map_err(|_| usage)?;
+    let minutes: u32 = minutes.parse().map_err(|_| usage)?;
+    if hours >= 24 || minutes >= 60 {
+      return Err(usage.into());
+    }
+
+    world.resource_mut::<Self>().hour = hours as f32 + minutes as f32 / 60.;
+    Ok(format!("It's {:02}:{:02}", hours, minutes))
+  }
+}
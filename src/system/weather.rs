@@ -0,0 +1,384 @@
+use crate::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// How long, in seconds, a new kind of weather takes to fully set in.
+const TRANSITION: f32 = 20.;
+/// How long each kind of weather lasts, in seconds.
+const DURATION: std::ops::Range<f32> = 90.0..240.;
+/// How long rain takes to soak the ground, and the ground takes to dry, in seconds.
+const SOAK: f32 = 30.;
+const DRY: f32 = 120.;
+/// Materials of the ground that look wet in the rain, by their `AssetCache` key.
+const WET_MATERIALS: [&str; 2] = ["grass", "yard/path"];
+/// Only touch the materials when the wetness has changed this much, they're uploaded again every
+/// time they change.
+const WETNESS_STEP: f32 = 0.05;
+
+const MAX_DROPS: usize = 400;
+/// Drops fall around the player, within this distance and below this height.
+const RAIN_RADIUS: f32 = 80.;
+const RAIN_HEIGHT: f32 = 60.;
+const RAIN_SPEED: f32 = 80.;
+
+const FOG_COLOR: Color = Color::rgb(0.6, 0.62, 0.65);
+/// Seconds between lightning flashes in a storm, and how long one lasts.
+const LIGHTNING_GAP: std::ops::Range<f32> = 4.0..15.;
+const LIGHTNING_FLASH: f32 = 0.15;
+const LIGHTNING_ILLUMINANCE: f32 = 60000.;
+/// How fast the wind blows in a storm, the strongest it gets.
+const STORM_WIND: f32 = 40.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherState {
+  Clear,
+  Rain,
+  Fog,
+  Storm,
+}
+
+impl WeatherState {
+  /// How much rain falls once it has fully set in, 0 to 1.
+  fn rain(self) -> f32 {
+    match self {
+      Self::Rain | Self::Storm => 1.,
+      _ => 0.,
+    }
+  }
+
+  /// How thick the fog gets once it has fully set in, 0 to 1.
+  fn fog(self) -> f32 {
+    match self {
+      Self::Fog => 0.7,
+      Self::Storm => 0.3,
+      Self::Rain => 0.15,
+      Self::Clear => 0.,
+    }
+  }
+
+  /// What the weather can turn into from this.
+  fn next(self) -> &'static [Self] {
+    match self {
+      Self::Clear => &[Self::Rain, Self::Fog, Self::Clear],
+      Self::Rain => &[Self::Clear, Self::Storm, Self::Fog],
+      Self::Fog => &[Self::Clear, Self::Rain],
+      Self::Storm => &[Self::Rain],
+    }
+  }
+}
+
+/// The current weather, changing every few minutes. Fades from the previous state to the current
+/// one over `TRANSITION` seconds. Everything about it comes from the world seed, since it changes
+/// what zombies see and hear.
+pub struct Weather {
+  pub state: WeatherState,
+  /// What it was before, fading out as the current state sets in.
+  pub previous: WeatherState,
+  /// 0 to 1, how far the current state has set in.
+  pub intensity: f32,
+  /// Seconds until the weather changes.
+  remaining: f32,
+  /// 0 to 1, how wet the ground is.
+  pub wetness: f32,
+  /// The wetness the ground materials were last changed for.
+  wetness_shown: f32,
+  /// Seconds until the next lightning flash.
+  lightning: f32,
+  /// The wind of the previous state and the current one, blended like the rest.
+  wind_from: Vec3,
+  wind_to: Vec3,
+  rng: StdRng,
+}
+
+impl Default for Weather {
+  fn default() -> Self {
+    Self::new(0)
+  }
+}
+
+/// Covers the view in fog. Bevy doesn't have distance fog yet, but from straight above everything
+/// is about as far from the camera anyway.
+#[derive(Component)]
+pub struct FogVeil;
+
+#[derive(Component)]
+pub struct RainDrop;
+
+#[derive(Component)]
+pub struct Lightning {
+  remaining: f32,
+}
+
+impl Weather {
+  /// A new world starts clear, then changes however `seed` has it.
+  pub fn new(seed: u64) -> Self {
+    Self {
+      state: WeatherState::Clear,
+      previous: WeatherState::Clear,
+      intensity: 1.,
+      remaining: DURATION.start,
+      wetness: 0.,
+      wetness_shown: 0.,
+      lightning: LIGHTNING_GAP.start,
+      wind_from: Vec3::ZERO,
+      wind_to: Vec3::ZERO,
+      rng: StdRng::seed_from_u64(seed ^ Salt::Weather as u64),
+    }
+  }
+
+  /// Some of the previous state and some of the current one, however far the change is along.
+  fn blend<T>(&self, of: impl Fn(WeatherState) -> T) -> T
+  where
+    T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>,
+  {
+    of(self.previous) * (1. - self.intensity) + of(self.state) * self.intensity
+  }
+
+  /// How much rain is falling, 0 to 1.
+  pub fn rain(&self) -> f32 {
+    self.blend(WeatherState::rain)
+  }
+
+  /// How thick the fog is, 0 to 1.
+  pub fn fog(&self) -> f32 {
+    self.blend(WeatherState::fog)
+  }
+
+  pub fn wind(&self) -> Vec3 {
+    self.wind_from * (1. - self.intensity) + self.wind_to * self.intensity
+  }
+
+  /// How hard the wind blows, 0 to 1.
+  pub fn wind_strength(&self) -> f32 {
+    (self.wind().length() / STORM_WIND).min(1.)
+  }
+
+  /// What zombie perception ranges are multiplied by, they can't see as far in fog or with the
+  /// wind blowing things about.
+  pub fn zombie_sight(&self) -> f32 {
+    (1. - self.fog() * 0.7) * (1. - self.wind_strength() * 0.2)
+  }
+
+  /// What the distance zombies hear noises from is multiplied by, rain and wind drown them out.
+  pub fn hearing(&self) -> f32 {
+    (1. - self.rain() * 0.5) * (1. - self.wind_strength() * 0.3)
+  }
+
+  /// Changes to whatever comes next, fading it in from wherever the weather is now.
+  fn change(&mut self) {
+    // changes are further apart than they take, so the last one is done by now
+    self.previous = self.state;
+    self.wind_from = self.wind_to;
+    self.state = *self.state.next().choose(&mut self.rng).unwrap();
+    self.intensity = 0.;
+    self.remaining = self.rng.gen_range(DURATION);
+
+    let angle = self.rng.gen_range(0.0..std::f32::consts::TAU);
+    let speed = match self.state {
+      WeatherState::Storm => STORM_WIND,
+      WeatherState::Rain => 10.,
+      _ => 5.,
+    };
+    self.wind_to = Vec3::new(angle.cos(), 0., angle.sin()) * speed;
+  }
+
+  /// A fresh world starts with clear weather.
+  pub fn reset(mut this: ResMut<Self>, seed: Res<WorldSeed>) {
+    *this = Self::new(seed.0);
+  }
+
+  pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera_query: Query<Entity, With<component::Camera>>,
+  ) {
+    for camera in &camera_query {
+      commands.entity(camera).with_children(|child_builder| {
+        child_builder
+          .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::splat(10.)))),
+            material: materials.add(StandardMaterial {
+              base_color: Color::NONE,
+              alpha_mode: AlphaMode::Blend,
+              unlit: true,
+              ..default()
+            }),
+            // just past the near plane
+            transform: Transform::from_xyz(0., 0., -1.),
+            ..default()
+          })
+          .insert(FogVeil);
+      });
+    }
+  }
+
+  pub fn update(
+    time: Res<Time>,
+    mut this: ResMut<Self>,
+    mut commands: Commands,
+    lightning_query: Query<(Entity, &mut Lightning)>,
+  ) {
+    let dt = time.delta_seconds();
+
+    this.remaining -= dt;
+    if this.remaining <= 0. {
+      this.change();
+    }
+    this.intensity = (this.intensity + dt / TRANSITION).min(1.);
+
+    let rain = this.rain();
+    this.wetness = match rain > 0. {
+      true => (this.wetness + rain * dt / SOAK).min(1.),
+      false => (this.wetness - dt / DRY).max(0.),
+    };
+
+    Self::update_lightning(&mut this, dt, &mut commands, lightning_query);
+  }
+
+  fn update_lightning(
+    this: &mut Self,
+    dt: f32,
+    commands: &mut Commands,
+    mut lightning_query: Query<(Entity, &mut Lightning)>,
+  ) {
+    for (entity, mut lightning) in &mut lightning_query {
+      lightning.remaining -= dt;
+      if lightning.remaining <= 0. {
+        commands.entity(entity).despawn_recursive();
+      }
+    }
+
+    if this.state != WeatherState::Storm || this.intensity < 1. {
+      return;
+    }
+    this.lightning -= dt;
+    if this.lightning > 0. {
+      return;
+    }
+    this.lightning = this.rng.gen_range(LIGHTNING_GAP);
+
+    commands
+      .spawn_bundle(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+          illuminance: LIGHTNING_ILLUMINANCE,
+          color: Color::rgb(0.85, 0.9, 1.),
+          ..default()
+        },
+        transform: Transform::default()
+          .looking_at(-Vec3::Y + this.wind().normalize_or_zero(), Vec3::X),
+        ..default()
+      })
+      .insert(Lightning {
+        remaining: LIGHTNING_FLASH,
+      });
+  }
+
+  pub fn update_fog(
+    this: Res<Self>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    veil_query: Query<&Handle<StandardMaterial>, With<FogVeil>>,
+  ) {
+    let fog = this.fog();
+    for handle in &veil_query {
+      // only touch the material when it would look different
+      let changed = materials
+        .get(handle)
+        .is_some_and(|material| (material.base_color.a() - fog).abs() > 0.01);
+      if let (true, Some(material)) = (changed, materials.get_mut(handle)) {
+        material.base_color = *FOG_COLOR.clone().set_a(fog);
+      }
+    }
+  }
+
+  /// Makes the ground shinier the wetter it is.
+  pub fn update_wetness(
+    mut this: ResMut<Self>,
+    cache: Res<AssetCache>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+  ) {
+    if (this.wetness - this.wetness_shown).abs() < WETNESS_STEP
+      && !(this.wetness == 0. && this.wetness_shown > 0.)
+    {
+      return;
+    }
+    this.wetness_shown = this.wetness;
+
+    for key in WET_MATERIALS {
+      let material = cache
+        .cached_material(key)
+        .and_then(|handle| materials.get_mut(handle));
+      if let Some(material) = material {
+        material.perceptual_roughness = 1. - this.wetness * 0.6;
+        material.reflectance = this.wetness * 0.4;
+      }
+    }
+  }
+
+  /// Keeps as many drops falling around the player as the rain calls for.
+  pub fn update_rain(
+    time: Res<Time>,
+    mut this: ResMut<Self>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cache: ResMut<AssetCache>,
+    player_query: Query<&Transform, (With<Player>, Without<RainDrop>)>,
+    mut drop_query: Query<(Entity, &mut Transform), With<RainDrop>>,
+  ) {
+    let center = match player_query.iter().next() {
+      Some(t) => t.translation,
+      None => return,
+    };
+    let wanted = (MAX_DROPS as f32 * this.rain()) as usize;
+    let mut count = 0;
+    let velocity = this.wind() - Vec3::Y * RAIN_SPEED;
+    let rng = &mut this.rng;
+    // the drops are long along y, lean them with the wind
+    let rotation = Quat::from_rotation_arc(Vec3::NEG_Y, velocity.normalize());
+    for (entity, mut t) in &mut drop_query {
+      count += 1;
+      if count > wanted {
+        commands.entity(entity).despawn_recursive();
+        continue;
+      }
+
+      t.translation += velocity * time.delta_seconds();
+      let offset = t.translation - center;
+      if t.translation.y < 0. || offset.x.abs() > RAIN_RADIUS || offset.z.abs() > RAIN_RADIUS {
+        *t = Self::drop_transform(center, RAIN_HEIGHT, rotation, rng);
+      }
+    }
+
+    if count >= wanted {
+      return;
+    }
+    let mesh = cache.mesh(&mut meshes, MeshKey::cuboid(0.1, 2., 0.1));
+    let material = cache.material(&mut materials, "weather/rain", || StandardMaterial {
+      base_color: Color::rgba(0.7, 0.8, 1., 0.5),
+      alpha_mode: AlphaMode::Blend,
+      unlit: true,
+      ..default()
+    });
+    // a few at a time, so the rain starts gradually
+    for _ in count..wanted.min(count + 20) {
+      commands
+        .spawn_bundle(PbrBundle {
+          mesh: mesh.clone(),
+          material: material.clone(),
+          transform: Self::drop_transform(center, rng.gen_range(0.0..RAIN_HEIGHT), rotation, rng),
+          ..default()
+        })
+        .insert(RainDrop);
+    }
+  }
+
+  /// Somewhere around `center` at `height` for a drop to fall from.
+  fn drop_transform(center: Vec3, height: f32, rotation: Quat, rng: &mut impl Rng) -> Transform {
+    let offset = Vec3::new(
+      rng.gen_range(-RAIN_RADIUS..RAIN_RADIUS),
+      height,
+      rng.gen_range(-RAIN_RADIUS..RAIN_RADIUS),
+    );
+    Transform::from_translation(center + offset).with_rotation(rotation)
+  }
+}
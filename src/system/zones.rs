@@ -1,23 +1,29 @@
 use crate::*;
 
-use lazy_static::lazy_static;
+use itertools::Itertools;
 
-const SIZE: f32 = 10000.;
-static WAIT: Duration = Duration::from_secs(5);
+const SIZE: f32 = 100.;
+const GRID: Grid = Grid::new(SIZE);
+/// How many rings of zones `nearest_nav_node` looks through before giving up.
+const NAV_SEARCH_RINGS: i16 = 5;
+/// Buildings closer than this are linked outside, across yards and roads.
+const OUTSIDE_LINK: f32 = 400.;
 
-lazy_static! {
-  static ref ZONE: (Sender<ZItem>, Receiver<ZItem>) = unbounded();
-  pub static ref ZONE_TX: Sender<ZItem> = ZONE.0.clone();
-}
-
-pub enum ZItem {
-  Building(Arc<Building>),
-  Nav(Arc<NavNode>),
-}
+/// Top level entities that moved since the last frame.
+type Moved<'w, 's> = Query<
+  'w,
+  's,
+  (Entity, &'static GlobalTransform),
+  (Changed<GlobalTransform>, Without<Parent>, Without<RainDrop>),
+>;
 
+/// A spatial index over the ground plane. Buildings are in every zone they overlap, nav nodes and
+/// entities in the zone of their position. Only top level entities are indexed, and not rain,
+/// which moves every frame and nothing looks for.
+#[derive(Default)]
 pub struct Zones {
-  pub zones: HashMap<(i16, i16), Zone>,
-  pub last_ran: Instant,
+  pub zones: HashMap<Coord, Zone>,
+  positions: HashMap<Entity, Vec3>,
 }
 
 #[derive(Default)]
@@ -29,13 +35,19 @@ pub struct Zone {
 
 impl Zones {
   #[inline]
-  pub fn transform_to_coord(t: &GlobalTransform) -> (i16, i16) {
+  pub fn transform_to_coord(t: &GlobalTransform) -> Coord {
     Self::translation_to_coord(&t.translation())
   }
 
   #[inline]
-  pub fn translation_to_coord(t: &Vec3) -> (i16, i16) {
-    ((t.z / SIZE) as i16, (t.x / SIZE) as i16)
+  pub fn translation_to_coord(t: &Vec3) -> Coord {
+    GRID.coord(t)
+  }
+
+  /// The area a zone covers.
+  pub fn zone_rect(coord: Coord) -> Rect {
+    let min = GRID.min(coord);
+    Rect::new(min.z, min.z + SIZE, min.x, min.x + SIZE)
   }
 
   #[inline]
@@ -48,6 +60,29 @@ impl Zones {
     self.zones.entry(Self::translation_to_coord(t)).or_default()
   }
 
+  /// Every zone coordinate touching the box from `min` to `max`.
+  fn coords_between(min: &Vec3, max: &Vec3) -> impl Iterator<Item = Coord> {
+    let (min, max) = (GRID.coord(min), GRID.coord(max));
+    (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| Coord { x, z }))
+  }
+
+  /// The area a building can have cells in: the bounding box of its bounds, plus room for cells
+  /// whose centers are on the edge, whichever way the building is turned.
+  fn building_area(building: &Building) -> Rect {
+    match building.bounds() {
+      Some(bounds) => {
+        let (min, max) = (bounds.min(), bounds.max());
+        Rect::new(
+          min.z - CELL_SIZE,
+          max.z + CELL_SIZE,
+          min.x - CELL_SIZE,
+          max.x + CELL_SIZE,
+        )
+      }
+      None => Rect::build(CELL_SIZE * 2., CELL_SIZE * 2.).center_at(&building.origin.translation),
+    }
+  }
+
   pub fn cell_at(&self, t: &Vec3) -> Option<&Arc<Cell>> {
     if let Some(zone) = self.zone(t) {
       for building in &zone.buildings {
@@ -59,49 +94,303 @@ impl Zones {
     None
   }
 
-  pub fn update(mut this: ResMut<Self>, query: Query<(Entity, &GlobalTransform)>) {
-    if this.last_ran.elapsed() < WAIT {
-      return;
+  /// Indexed entities within `radius` of `pos` on the ground plane.
+  pub fn entities_within(&self, pos: &Vec3, radius: f32) -> Vec<Entity> {
+    let r = Vec3::new(radius, 0., radius);
+    Self::coords_between(&(*pos - r), &(*pos + r))
+      .filter_map(|coord| self.zones.get(&coord))
+      .flat_map(|zone| &zone.entities)
+      .filter(|e| {
+        self
+          .positions
+          .get(e)
+          .is_some_and(|p| Vec3::new(p.x - pos.x, 0., p.z - pos.z).length() <= radius)
+      })
+      .copied()
+      .collect()
+  }
+
+  /// The closest nav node to `pos`, searching outward a ring of zones at a time.
+  pub fn nearest_nav_node(&self, pos: &Vec3) -> Option<&Arc<NavNode>> {
+    self.nearest_nav_node_matching(pos, |_| true)
+  }
+
+  /// The closest nav node to `pos` that `filter` accepts.
+  pub fn nearest_nav_node_matching(
+    &self,
+    pos: &Vec3,
+    filter: impl Fn(&NavNode) -> bool,
+  ) -> Option<&Arc<NavNode>> {
+    let Coord { x: cx, z: cz } = Self::translation_to_coord(pos);
+    let mut nearest: Option<(&Arc<NavNode>, f32)> = None;
+
+    for ring in 0..=NAV_SEARCH_RINGS {
+      for z in (cz - ring)..=(cz + ring) {
+        for x in (cx - ring)..=(cx + ring) {
+          // just the edge of the ring, the inside has been searched already
+          if (z - cz).abs() != ring && (x - cx).abs() != ring {
+            continue;
+          }
+          let zone = match self.zones.get(&Coord { x, z }) {
+            Some(zone) => zone,
+            None => continue,
+          };
+          for node in zone.nav_nodes.iter().filter(|node| filter(node)) {
+            let dist = node.pos.distance(*pos);
+            // ties go to the older node, whichever order the zone holds them in
+            if nearest.is_none_or(|(n, d)| dist < d || dist == d && node.id() < n.id()) {
+              nearest = Some((node, dist));
+            }
+          }
+        }
+      }
+
+      // anything in the next ring is at least this far away
+      if let Some((_, dist)) = nearest {
+        if dist <= ring as f32 * SIZE {
+          break;
+        }
+      }
+    }
+
+    nearest.map(|(node, _)| node)
+  }
+
+  pub fn buildings_overlapping(&self, rect: &Rect) -> Vec<&Arc<Building>> {
+    let mut seen = HashSet::new();
+    Self::coords_between(&rect.min(), &rect.max())
+      .filter_map(|coord| self.zones.get(&coord))
+      .flat_map(|zone| &zone.buildings)
+      .filter(|b| Self::building_area(b).overlaps(rect) && seen.insert(Arc::as_ptr(b)))
+      .collect()
+  }
+
+  /// Moves an entity to the zone of `pos`, adding it if it isn't indexed yet.
+  pub fn set_entity(&mut self, entity: Entity, pos: Vec3) {
+    let coord = Self::translation_to_coord(&pos);
+    if let Some(old) = self.positions.insert(entity, pos) {
+      let old = Self::translation_to_coord(&old);
+      if old == coord {
+        return;
+      }
+      if let Some(zone) = self.zones.get_mut(&old) {
+        zone.entities.remove(&entity);
+      }
+    }
+    self.zones.entry(coord).or_default().entities.insert(entity);
+  }
+
+  pub fn remove_entity(&mut self, entity: Entity) {
+    if let Some(pos) = self.positions.remove(&entity) {
+      if let Some(zone) = self.zones.get_mut(&Self::translation_to_coord(&pos)) {
+        zone.entities.remove(&entity);
+      }
+    }
+  }
+
+  /// The nodes just outside a building's doors.
+  fn outside_nodes(building: &Building) -> Vec<Arc<NavNode>> {
+    building
+      .cells
+      .values()
+      .flat_map(|cell| {
+        cell
+          .nav_nodes
+          .read()
+          .iter()
+          .flatten()
+          .cloned()
+          .collect::<Vec<_>>()
+      })
+      .filter(|node| node.r#type == NavNodeType::Outside)
+      .collect()
+  }
+
+  /// Links the closest pair of outside nodes between `building` and every indexed building near it,
+  /// so a path can lead out of one building and into the next.
+  fn link_outside(&self, building: &Arc<Building>) {
+    let (min, max) = {
+      let area = Self::building_area(building);
+      (area.min(), area.max())
+    };
+    let near = Rect::new(
+      min.z - OUTSIDE_LINK,
+      max.z + OUTSIDE_LINK,
+      min.x - OUTSIDE_LINK,
+      max.x + OUTSIDE_LINK,
+    );
+    let ours = Self::outside_nodes(building);
+
+    for other in self.buildings_overlapping(&near) {
+      if Arc::ptr_eq(other, building) {
+        continue;
+      }
+      let closest = ours
+        .iter()
+        .cartesian_product(Self::outside_nodes(other))
+        .min_by(|(a, b), (c, d)| {
+          let (ab, cd) = (a.pos.distance(b.pos), c.pos.distance(d.pos));
+          // ties by id, so links don't depend on hash order
+          ab.total_cmp(&cd)
+            .then((a.id(), b.id()).cmp(&(c.id(), d.id())))
+        });
+      if let Some((a, b)) = closest {
+        a.adj.write().insert(b.clone());
+        b.adj.write().insert(a.clone());
+      }
     }
-    this.last_ran = Instant::now();
+  }
 
-    // clear out the entities
-    for zone in this.zones.values_mut() {
-      zone.entities = HashSet::new();
+  /// Indexes a building along with its navigation, used when its chunk is loaded.
+  pub fn insert_building(&mut self, building: Arc<Building>) {
+    self.link_outside(&building);
+    for cell in building.cells.values() {
+      for node in cell.nav_nodes.read().iter().flatten() {
+        self
+          .zone_or_create_mut(&node.pos)
+          .nav_nodes
+          .insert(node.clone());
+      }
     }
-    for (e, t) in query.iter() {
-      this
+
+    let area = Self::building_area(&building);
+    for coord in Self::coords_between(&area.min(), &area.max()) {
+      self
         .zones
-        .entry(Self::transform_to_coord(t))
+        .entry(coord)
         .or_default()
-        .entities
-        .insert(e);
+        .buildings
+        .insert(building.clone());
     }
+  }
 
-    for item in ZONE.1.try_iter() {
-      match item {
-        ZItem::Building(b) => {
-          let zone = this.zone_or_create_mut(&b.origin.translation);
-          zone.buildings.insert(b);
-        }
-        ZItem::Nav(n) => {
-          let zone = this.zone_or_create_mut(&n.pos);
-          zone.nav_nodes.insert(n);
+  /// Forgets a building and its navigation, used when its chunk is unloaded.
+  pub fn remove_building(&mut self, building: &Arc<Building>) {
+    let area = Self::building_area(building);
+    for coord in Self::coords_between(&area.min(), &area.max()) {
+      if let Some(zone) = self.zones.get_mut(&coord) {
+        zone.buildings.remove(building);
+      }
+    }
+
+    for cell in building.cells.values() {
+      for node in cell.nav_nodes.read().iter().flatten() {
+        if let Some(zone) = self.zones.get_mut(&Self::translation_to_coord(&node.pos)) {
+          zone.nav_nodes.remove(node);
         }
       }
     }
 
-    // for (k, v) in &zones.entities {
-    // println!("{:?}: {}", k, v.len());
-    // }
+    // unlink it from the buildings it was linked to outside
+    for node in Self::outside_nodes(building) {
+      let mut adj = node.adj.write();
+      for other in adj.iter().filter(|n| n.r#type == NavNodeType::Outside) {
+        other.adj.write().remove(&node);
+      }
+      adj.retain(|n| n.r#type != NavNodeType::Outside);
+    }
+  }
+
+  /// Forget everything, for a new world.
+  pub fn clear(&mut self) {
+    *self = Self::default();
+  }
+
+  /// Keeps the entities up to date with whatever moved, spawned or despawned since the last frame.
+  pub fn update(mut this: ResMut<Self>, moved: Moved, removed: RemovedComponents<GlobalTransform>) {
+    for e in removed.iter() {
+      this.remove_entity(e);
+    }
+    for (e, t) in moved.iter() {
+      this.set_entity(e, t.translation());
+    }
   }
 }
 
-impl Default for Zones {
-  fn default() -> Self {
-    Self {
-      zones: HashMap::default(),
-      last_ran: Instant::now() - WAIT,
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negative_coords_round_down() {
+    let coord = |x, z| Zones::translation_to_coord(&Vec3::new(x, 0., z));
+    assert_eq!(coord(0.5, 0.5), Coord { x: 0, z: 0 });
+    assert_eq!(coord(-0.5, -0.5), Coord { x: -1, z: -1 });
+    assert_eq!(coord(-SIZE, SIZE), Coord { x: -1, z: 1 });
+    assert_eq!(coord(-SIZE - 0.1, -SIZE + 0.1), Coord { x: -2, z: -1 });
+  }
+
+  #[test]
+  fn entities_within_across_zero() {
+    let mut zones = Zones::default();
+    let a = Entity::from_raw(0);
+    let b = Entity::from_raw(1);
+    let c = Entity::from_raw(2);
+    zones.set_entity(a, Vec3::new(-1., 0., -1.));
+    zones.set_entity(b, Vec3::new(1., 0., 1.));
+    zones.set_entity(c, Vec3::new(-50., 0., 0.));
+
+    let mut near = zones.entities_within(&Vec3::ZERO, 5.);
+    near.sort();
+    assert_eq!(near, vec![a, b]);
+    assert_eq!(zones.entities_within(&Vec3::new(-45., 0., 0.), 5.), vec![c]);
+  }
+
+  #[test]
+  fn moved_entities_leave_their_old_zone() {
+    let mut zones = Zones::default();
+    let e = Entity::from_raw(0);
+    zones.set_entity(e, Vec3::new(-10., 0., -10.));
+    zones.set_entity(e, Vec3::new(10., 0., 10.));
+
+    assert!(zones
+      .entities_within(&Vec3::new(-10., 0., -10.), 1.)
+      .is_empty());
+    assert_eq!(zones.entities_within(&Vec3::new(10., 0., 10.), 1.), vec![e]);
+
+    zones.remove_entity(e);
+    assert!(zones
+      .entities_within(&Vec3::new(10., 0., 10.), 1.)
+      .is_empty());
+  }
+
+  #[test]
+  fn paths_lead_between_buildings() {
+    let nodes = |building: &Building, r#type| {
+      building
+        .cells
+        .keys()
+        .sorted()
+        .flat_map(|coord| {
+          building.cells[coord]
+            .nav_nodes
+            .read()
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+        })
+        .filter(|node: &Arc<NavNode>| node.r#type == r#type)
+        .collect::<Vec<_>>()
+    };
+    let a = Building::generate(Transform::default(), 1, &[]);
+    let b = Building::generate(Transform::from_xyz(300., 0., 0.), 2, &[]);
+    let mut zones = Zones::default();
+    zones.insert_building(a.clone());
+    zones.insert_building(b.clone());
+
+    let (from, to) = (
+      &nodes(&a, NavNodeType::Cell)[0],
+      &nodes(&b, NavNodeType::Cell)[0],
+    );
+    let mut nav = Navigator::new(from, to);
+    nav.nav();
+    assert!(nav.found());
+
+    zones.remove_building(&b);
+    let b_nodes = nodes(&b, NavNodeType::Outside);
+    for node in nodes(&a, NavNodeType::Outside) {
+      assert!(node.adj.read().iter().all(|adj| !b_nodes.contains(adj)));
     }
   }
 }
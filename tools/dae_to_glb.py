@@ -0,0 +1,186 @@
+#!/usr/bin/env python3
+"""Converts the Collada tree models in assets/trees to binary glTF, which Bevy can load.
+
+Only handles what those models use: one polylist per geometry with positions, normals and one
+set of texture coordinates, placed by a node matrix in a Z-up scene. The colour sheet texture is
+embedded so every .glb stands on its own.
+
+    python3 tools/dae_to_glb.py [--texture PATH] FILE.dae...
+
+Writes FILE.glb next to every FILE.dae.
+"""
+
+import argparse
+import json
+import os
+import struct
+import xml.etree.ElementTree as ET
+
+NS = {"c": "http://www.collada.org/2005/11/COLLADASchema"}
+DEFAULT_TEXTURE = os.path.join(
+    os.path.dirname(__file__), "..", "assets", "trees", "Textures", "Colorsheet Tree Normal.png"
+)
+
+
+def floats(el):
+    return [float(v) for v in el.text.split()]
+
+
+def ints(el):
+    return [int(v) for v in el.text.split()]
+
+
+def source_data(mesh, source_id):
+    source = mesh.find(f"c:source[@id='{source_id.lstrip('#')}']", NS)
+    stride = int(source.find("c:technique_common/c:accessor", NS).get("stride"))
+    data = floats(source.find("c:float_array", NS))
+    return [data[i : i + stride] for i in range(0, len(data), stride)]
+
+
+def mat_mul_point(m, p, w):
+    return [sum(m[r * 4 + c] * (p[c] if c < 3 else w) for c in range(4)) for r in range(3)]
+
+
+def z_up_to_y_up(p):
+    return [p[0], p[2], -p[1]]
+
+
+def normalize(v):
+    length = sum(c * c for c in v) ** 0.5 or 1.0
+    return [c / length for c in v]
+
+
+def read_mesh(path):
+    root = ET.parse(path).getroot()
+    up = root.findtext("c:asset/c:up_axis", "Y_UP", NS)
+
+    node = root.find(".//c:visual_scene//c:node[c:instance_geometry]", NS)
+    matrix_el = node.find("c:matrix", NS)
+    matrix = floats(matrix_el) if matrix_el is not None else [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1]
+    geometry_id = node.find("c:instance_geometry", NS).get("url").lstrip("#")
+    mesh = root.find(f"c:library_geometries/c:geometry[@id='{geometry_id}']/c:mesh", NS)
+
+    vertices = mesh.find("c:vertices", NS)
+    position_source = vertices.find("c:input[@semantic='POSITION']", NS).get("source")
+    positions = source_data(mesh, position_source)
+
+    polylist = mesh.find("c:polylist", NS)
+    inputs = {i.get("semantic"): i for i in polylist.findall("c:input", NS)}
+    stride = max(int(i.get("offset")) for i in inputs.values()) + 1
+    normals = source_data(mesh, inputs["NORMAL"].get("source"))
+    uvs = source_data(mesh, inputs["TEXCOORD"].get("source"))
+    offsets = {k: int(v.get("offset")) for k, v in inputs.items()}
+
+    vcount = ints(polylist.find("c:vcount", NS))
+    p = ints(polylist.find("c:p", NS))
+
+    def corner(i):
+        base = i * stride
+        pos = mat_mul_point(matrix, positions[p[base + offsets["VERTEX"]]], 1.0)
+        normal = mat_mul_point(matrix, normals[p[base + offsets["NORMAL"]]], 0.0)
+        if up == "Z_UP":
+            pos, normal = z_up_to_y_up(pos), z_up_to_y_up(normal)
+        u, v = uvs[p[base + offsets["TEXCOORD"]]][:2]
+        return pos, normalize(normal), [u, 1.0 - v]
+
+    out = []
+    i = 0
+    for n in vcount:
+        polygon = [corner(i + k) for k in range(n)]
+        # fan triangulation, the polygons are convex
+        for k in range(1, n - 1):
+            out += [polygon[0], polygon[k], polygon[k + 1]]
+        i += n
+    return out
+
+
+def pad(data, fill=b"\0"):
+    return data + fill * (-len(data) % 4)
+
+
+def write_glb(path, corners, texture):
+    name = os.path.splitext(os.path.basename(path))[0]
+    positions = b"".join(struct.pack("<3f", *c[0]) for c in corners)
+    normals = b"".join(struct.pack("<3f", *c[1]) for c in corners)
+    uvs = b"".join(struct.pack("<2f", *c[2]) for c in corners)
+    image = pad(texture)
+
+    views = []
+    binary = b""
+    for chunk, target in [(positions, 34962), (normals, 34962), (uvs, 34962), (image, None)]:
+        view = {"buffer": 0, "byteOffset": len(binary), "byteLength": len(chunk)}
+        if target:
+            view["target"] = target
+        views.append(view)
+        binary += chunk
+    views[3]["byteLength"] = len(texture)
+
+    xs, ys, zs = zip(*(c[0] for c in corners))
+    gltf = {
+        "asset": {"version": "2.0", "generator": "tools/dae_to_glb.py"},
+        "scene": 0,
+        "scenes": [{"nodes": [0]}],
+        "nodes": [{"name": name, "mesh": 0}],
+        "meshes": [
+            {
+                "name": name,
+                "primitives": [
+                    {"attributes": {"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2}, "material": 0}
+                ],
+            }
+        ],
+        "materials": [
+            {
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": {"index": 0},
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                }
+            }
+        ],
+        "textures": [{"source": 0, "sampler": 0}],
+        # nearest filtering keeps the colour sheet swatches from bleeding into each other
+        "samplers": [{"magFilter": 9728, "minFilter": 9728}],
+        "images": [{"bufferView": 3, "mimeType": "image/png"}],
+        "accessors": [
+            {
+                "bufferView": 0,
+                "componentType": 5126,
+                "count": len(corners),
+                "type": "VEC3",
+                "min": [min(xs), min(ys), min(zs)],
+                "max": [max(xs), max(ys), max(zs)],
+            },
+            {"bufferView": 1, "componentType": 5126, "count": len(corners), "type": "VEC3"},
+            {"bufferView": 2, "componentType": 5126, "count": len(corners), "type": "VEC2"},
+        ],
+        "bufferViews": views,
+        "buffers": [{"byteLength": len(binary)}],
+    }
+
+    json_chunk = pad(json.dumps(gltf, separators=(",", ":")).encode(), b" ")
+    bin_chunk = pad(binary)
+    total = 12 + 8 + len(json_chunk) + 8 + len(bin_chunk)
+    with open(path, "wb") as f:
+        f.write(struct.pack("<4sII", b"glTF", 2, total))
+        f.write(struct.pack("<I4s", len(json_chunk), b"JSON") + json_chunk)
+        f.write(struct.pack("<I4s", len(bin_chunk), b"BIN\0") + bin_chunk)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--texture", default=DEFAULT_TEXTURE, help="PNG colour sheet to embed")
+    parser.add_argument("files", nargs="+")
+    args = parser.parse_args()
+
+    with open(args.texture, "rb") as f:
+        texture = f.read()
+
+    for path in args.files:
+        out = os.path.splitext(path)[0] + ".glb"
+        write_glb(out, read_mesh(path), texture)
+        print(f"{path} -> {out}")
+
+
+if __name__ == "__main__":
+    main()